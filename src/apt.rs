@@ -1,115 +1,376 @@
+use crate::cache::Cache;
 use crate::crypto;
 use crate::deb::{self, Pkg};
 use crate::errors::*;
 use crate::http;
 use crate::pgp;
 use crate::progress::ProgressBar;
-use sha2::{Digest, Sha256};
-use std::fs;
-use std::path::Path;
+use libflate::gzip::Decoder;
+use lzma::LzmaReader;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::io::AsyncWriteExt;
 
 pub const DEFAULT_DOWNLOAD_ATTEMPTS: usize = 5;
-pub const SPOTIFY_REPOSITORY: &str = "https://repository.spotify.com";
-pub const SPOTIFY_REPOSITORY_RELEASE: &str = "testing";
+pub const DEFAULT_REPOSITORY_URL: &str = "https://repository.spotify.com";
+pub const DEFAULT_REPOSITORY_SUITE: &str = "testing";
+
+/// The server sent more data than the signed package index allows
+#[derive(Debug)]
+pub struct SizeLimitExceeded {
+    pub limit: u64,
+}
+
+impl fmt::Display for SizeLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Download exceeds the signed size of {} bytes, aborting",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for SizeLimitExceeded {}
+
+fn check_size(size: u64, limit: Option<u64>) -> Result<()> {
+    match limit {
+        Some(limit) if size > limit => Err(SizeLimitExceeded { limit }.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Verify a completed download against everything the signed package index tells us about it
+pub fn verify_download(pkg: &Pkg, size: u64, hashes: &crypto::Hashes) -> Result<()> {
+    if let Some(signed_size) = pkg.size {
+        info!("Verifying download size...");
+        if size != signed_size {
+            bail!(
+                "Downloaded size doesn't match signed size (signed: {}, downloaded: {})",
+                signed_size,
+                size
+            );
+        }
+    }
+
+    info!("Verifying with sha256sum hash...");
+    if pkg.sha256sum != hashes.sha256 {
+        bail!(
+            "Downloaded bytes don't match signed sha256sum (signed: {:?}, downloaded: {:?})",
+            pkg.sha256sum,
+            hashes.sha256
+        );
+    }
+
+    if let Some(sha512sum) = &pkg.sha512sum {
+        info!("Verifying with sha512sum hash...");
+        if *sha512sum != hashes.sha512 {
+            bail!(
+                "Downloaded bytes don't match signed sha512sum (signed: {:?}, downloaded: {:?})",
+                sha512sum,
+                hashes.sha512
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Xz,
+    Gzip,
+    None,
+}
+
+impl Compression {
+    /// Supported compression formats, in order of preference
+    pub const PREFERENCE: [Compression; 3] =
+        [Compression::Xz, Compression::Gzip, Compression::None];
+
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Compression::Xz => ".xz",
+            Compression::Gzip => ".gz",
+            Compression::None => "",
+        }
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        match self {
+            Compression::Xz => {
+                let mut decoder = LzmaReader::new_decompressor(data)?;
+                decoder
+                    .read_to_end(&mut buf)
+                    .context("Failed to decompress xz data")?;
+            }
+            Compression::Gzip => {
+                let mut decoder = Decoder::new(data)?;
+                decoder
+                    .read_to_end(&mut buf)
+                    .context("Failed to decompress gzip data")?;
+            }
+            Compression::None => buf.extend(data),
+        }
+        Ok(buf)
+    }
+}
+
+#[derive(Debug)]
+pub struct PkgIndex {
+    pub release_date: Option<SystemTime>,
+    /// The signature of the release file the index was loaded from
+    pub signature: pgp::SignatureInfo,
+    pub pkgs: Vec<Pkg>,
+}
+
+impl PkgIndex {
+    /// Select the spotify-client package with the highest version
+    pub fn spotify_client(&self) -> Result<&Pkg> {
+        let pkg = self
+            .pkgs
+            .iter()
+            .filter(|p| p.package == "spotify-client")
+            .filter_map(|p| match p.version.parse::<deb::Version>() {
+                Ok(version) => Some((version, p)),
+                Err(err) => {
+                    warn!("Ignoring package with invalid version: {err:#}");
+                    None
+                }
+            })
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, p)| p)
+            .context("Repository didn't contain spotify-client")?;
+        debug!("Found package: {:?}", pkg);
+        Ok(pkg)
+    }
+
+    /// Select the spotify-client package with exactly this version
+    pub fn spotify_client_version(&self, version: &deb::Version) -> Result<&Pkg> {
+        let mut available = Vec::new();
+        for pkg in self.pkgs.iter().filter(|p| p.package == "spotify-client") {
+            if pkg.version.parse::<deb::Version>().ok().as_ref() == Some(version) {
+                debug!("Found package: {:?}", pkg);
+                return Ok(pkg);
+            }
+            available.push(pkg.version.as_str());
+        }
+        bail!(
+            "Pinned version {} is not available in the repository (available: {:?})",
+            version,
+            available
+        );
+    }
+
+    /// Select the pinned version if there is one, otherwise the newest version
+    pub fn select_spotify_client(&self, pinned: Option<&deb::Version>) -> Result<&Pkg> {
+        if let Some(version) = pinned {
+            self.spotify_client_version(version)
+        } else {
+            self.spotify_client()
+        }
+    }
+}
 
 pub struct Client {
     client: http::Client,
+    repository_url: String,
+    suite: String,
+    arch: deb::Architecture,
+    verifier: Box<dyn pgp::Verifier>,
 }
 
 impl Client {
-    pub fn new(timeout: Option<u64>) -> Result<Client> {
+    pub fn new(
+        timeout: Option<u64>,
+        repository_url: &str,
+        suite: &str,
+        arch: deb::Architecture,
+        verifier: Box<dyn pgp::Verifier>,
+    ) -> Result<Client> {
         let client = http::Client::new(timeout)?;
-        Ok(Client { client })
+        let repository_url = repository_url.trim_end_matches('/').to_string();
+        if repository_url.is_empty() {
+            bail!("Repository url must not be empty");
+        }
+        if suite.is_empty() || suite.contains('/') {
+            bail!("Invalid repository suite: {:?}", suite);
+        }
+        Ok(Client {
+            client,
+            repository_url,
+            suite: suite.to_string(),
+            arch,
+            verifier,
+        })
     }
 
-    pub async fn fetch_pkg_release(&self, keyring_path: &Path) -> Result<Pkg> {
-        info!("Downloading release file...");
-        let release = self
-            .client
-            .fetch(&format!(
-                "{SPOTIFY_REPOSITORY}/dists/{SPOTIFY_REPOSITORY_RELEASE}/Release"
-            ))
-            .await?;
+    pub fn repository_url(&self) -> &str {
+        &self.repository_url
+    }
 
-        info!("Downloading signature...");
-        let sig = self
-            .client
-            .fetch(&format!(
-                "{SPOTIFY_REPOSITORY}/dists/{SPOTIFY_REPOSITORY_RELEASE}/Release.gpg"
-            ))
-            .await?;
+    pub fn suite(&self) -> &str {
+        &self.suite
+    }
 
-        info!("Verifying pgp signature...");
-        let tmp = tempfile::tempdir().context("Failed to create temporary directory")?;
-        let tmp_path = tmp.path();
+    pub fn arch(&self) -> &deb::Architecture {
+        &self.arch
+    }
 
-        let artifact_path = tmp_path.join("artifact");
-        fs::write(&artifact_path, &release)?;
-        let sig_path = tmp_path.join("sig");
-        fs::write(&sig_path, &sig)?;
+    fn dists_url(&self, path: &str) -> String {
+        format!("{}/dists/{}/{}", self.repository_url, self.suite, path)
+    }
 
-        pgp::verify_sig::<&Path>(&sig_path, &artifact_path, keyring_path).await?;
+    pub fn download_url(&self, pkg: &Pkg) -> String {
+        format!("{}/{}", self.repository_url, pkg.filename)
+    }
+
+    async fn verify_detached(&self, artifact: &[u8], sig: &[u8]) -> Result<pgp::SignatureInfo> {
+        info!("Verifying pgp signature...");
+        let signature = self.verifier.verify(sig, artifact).await?;
 
         info!("Signature verified successfully!");
-        let release = deb::parse_release_file(&String::from_utf8(release)?)?;
-        let arch = deb::Architecture::current();
-        let debian_arch_str = arch.to_debian_str();
+        Ok(signature)
+    }
+
+    async fn fetch_inrelease(&self) -> Result<Option<(String, pgp::SignatureInfo)>> {
+        info!("Downloading InRelease file...");
+        let inrelease = match self.client.fetch(&self.dists_url("InRelease")).await {
+            Ok(inrelease) => inrelease,
+            Err(err) => {
+                info!("Failed to download InRelease file, falling back to Release file: {err:#}");
+                return Ok(None);
+            }
+        };
+
+        let inrelease = String::from_utf8(inrelease)?;
+        let clearsigned =
+            pgp::parse_clearsigned(&inrelease).context("Failed to parse InRelease file")?;
+        let signature = self
+            .verify_detached(
+                clearsigned.text.as_bytes(),
+                clearsigned.signature.as_bytes(),
+            )
+            .await
+            .context("Failed to verify InRelease file")?;
+
+        Ok(Some((clearsigned.text, signature)))
+    }
+
+    async fn fetch_release(&self) -> Result<(String, pgp::SignatureInfo)> {
+        if let Some(release) = self.fetch_inrelease().await? {
+            return Ok(release);
+        }
+
+        info!("Downloading release file...");
+        let release = self.client.fetch(&self.dists_url("Release")).await?;
+
+        info!("Downloading signature...");
+        let sig = self.client.fetch(&self.dists_url("Release.gpg")).await?;
+
+        let signature = self.verify_detached(&release, &sig).await?;
+        Ok((String::from_utf8(release)?, signature))
+    }
+
+    pub async fn fetch_pkg_index(&self, last_release_date: Option<SystemTime>) -> Result<PkgIndex> {
+        let (release, signature) = self.fetch_release().await?;
+        let release = deb::parse_release_file(&release)?;
+        release
+            .verify_freshness(SystemTime::now(), last_release_date)
+            .context("Refusing to use outdated release file")?;
+        let debian_arch_str = self.arch.to_debian_str();
 
         if !release.architectures.iter().any(|a| a == debian_arch_str) {
             bail!(
-                "There are no packages for your cpu's architecture (cpu={:?}, supported={:?})",
+                "There are no packages for your architecture (arch={:?}, supported={:?})",
                 debian_arch_str,
                 release.architectures
             )
         }
 
-        let packages_path = format!("non-free/binary-{debian_arch_str}/Packages");
+        let pkg_index = self.fetch_packages_file(&release, debian_arch_str).await?;
+        let pkgs = deb::parse_package_index(&String::from_utf8(pkg_index)?)?;
+        debug!("Parsed package index: {:?}", pkgs);
 
-        let packages_sha256sum = release
-            .sha256_sums
-            .get(&packages_path)
-            .context("Missing sha256sum for package index")?;
+        Ok(PkgIndex {
+            release_date: release.date,
+            signature,
+            pkgs,
+        })
+    }
 
-        info!("Downloading package index...");
-        let pkg_index = self
-            .client
-            .fetch(&format!(
-                "{SPOTIFY_REPOSITORY}/dists/{SPOTIFY_REPOSITORY_RELEASE}/{packages_path}"
-            ))
-            .await?;
+    async fn fetch_packages_file(&self, release: &deb::Release, arch: &str) -> Result<Vec<u8>> {
+        let packages_path = format!("non-free/binary-{arch}/Packages");
 
-        info!("Verifying with sha256sum hash...");
-        let downloaded_sha256sum = crypto::sha256sum(&pkg_index);
-        if *packages_sha256sum != downloaded_sha256sum {
-            bail!(
-                "Downloaded bytes don't match signed sha256sum (signed: {:?}, downloaded: {:?})",
-                packages_sha256sum,
-                downloaded_sha256sum
-            );
+        let candidates = Compression::PREFERENCE
+            .iter()
+            .filter_map(|compression| {
+                let path = format!("{packages_path}{}", compression.extension());
+                let sha256sum = release.sha256_sums.get(&path)?;
+                Some((compression, path, sha256sum))
+            })
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            bail!("Missing sha256sum for package index");
         }
 
-        let pkg_index = deb::parse_package_index(&String::from_utf8(pkg_index)?)?;
-        debug!("Parsed package index: {:?}", pkg_index);
-        let pkg = pkg_index
-            .into_iter()
-            .find(|p| p.package == "spotify-client")
-            .context("Repository didn't contain spotify-client")?;
+        for (i, (compression, path, packages_sha256sum)) in candidates.iter().enumerate() {
+            info!("Downloading package index ({path:?})...");
+            let pkg_index = match self.client.fetch(&self.dists_url(path)).await {
+                Ok(pkg_index) => pkg_index,
+                Err(err) if i + 1 < candidates.len() => {
+                    warn!("Failed to download package index, trying next one: {err:#}");
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
 
-        debug!("Found package: {:?}", pkg);
-        Ok(pkg)
+            info!("Verifying with sha256sum hash...");
+            let downloaded_sha256sum = crypto::sha256sum(&pkg_index);
+            if **packages_sha256sum != downloaded_sha256sum {
+                bail!(
+                    "Downloaded bytes don't match signed sha256sum (signed: {:?}, downloaded: {:?})",
+                    packages_sha256sum,
+                    downloaded_sha256sum
+                );
+            }
+
+            return compression
+                .decompress(&pkg_index)
+                .with_context(|| anyhow!("Failed to decompress package index {path:?}"));
+        }
+
+        bail!("Failed to download package index");
     }
 
     async fn attempt_download(
         &self,
         url: &str,
-        deb: &mut Vec<u8>,
-        hasher: &mut Sha256,
+        file: &mut tokio::fs::File,
+        hasher: &mut crypto::Hasher,
         pb: &mut ProgressBar,
         offset: &mut Option<u64>,
+        max_size: Option<u64>,
     ) -> Result<()> {
         let mut dl = self.client.fetch_stream(url, *offset).await?;
+        if offset.is_some() && dl.progress == 0 {
+            warn!("Download server does not support resumption, restarting download");
+            file.set_len(0)
+                .await
+                .context("Failed to truncate download file")?;
+            *hasher = crypto::Hasher::default();
+            *offset = None;
+        }
+        check_size(dl.total, max_size)?;
         while let Some(chunk) = dl.chunk().await? {
-            deb.extend(&chunk);
+            check_size(dl.progress, max_size)?;
+            file.write_all(&chunk)
+                .await
+                .context("Failed to write to download file")?;
             hasher.update(&chunk);
             *offset = Some(dl.progress);
 
@@ -120,10 +381,48 @@ impl Client {
                 progress, dl.progress, dl.total
             );
         }
+        file.flush().await?;
         Ok(())
     }
 
-    pub async fn download_pkg(&self, pkg: &Pkg, max_download_attempts: usize) -> Result<Vec<u8>> {
+    /// Open the partial download file and hash the data that's already in there
+    async fn resume_partial_file(
+        path: &Path,
+        hasher: &mut crypto::Hasher,
+        max_size: Option<u64>,
+    ) -> Result<(tokio::fs::File, u64)> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .await
+            .with_context(|| anyhow!("Failed to open download file at {:?}", path))?;
+
+        let len = file.metadata().await?.len();
+        if check_size(len, max_size).is_err() {
+            warn!("Partial download at {:?} is too large, discarding", path);
+            file.set_len(0).await?;
+            return Ok((file, 0));
+        }
+
+        if len > 0 {
+            info!("Resuming previous download at {} bytes", len);
+            crypto::hash_reader(&mut file, |bytes| hasher.update(bytes))
+                .await
+                .with_context(|| anyhow!("Failed to read download file at {:?}", path))?;
+        }
+
+        Ok((file, len))
+    }
+
+    /// Download the .deb into the cache, verify it and return its path
+    pub async fn download_pkg(
+        &self,
+        pkg: &Pkg,
+        max_download_attempts: usize,
+        cache: &Cache,
+    ) -> Result<PathBuf> {
         let filename = pkg
             .filename
             .rsplit_once('/')
@@ -134,21 +433,34 @@ impl Client {
             "Downloading deb file for {:?} version={:?} ({:?})",
             filename, pkg.package, pkg.version
         );
-        let url = pkg.download_url();
+        let url = self.download_url(pkg);
+        if pkg.size.is_none() {
+            warn!("Package index doesn't specify a size for this package, download is unbounded");
+        }
+
+        cache.create_dir().await?;
+        let part_path = cache.part_path(&pkg.sha256sum)?;
+        let deb_path = cache.entry_path(&pkg.sha256sum)?;
+
+        let mut hasher = crypto::Hasher::default();
+        let (mut file, len) = Self::resume_partial_file(&part_path, &mut hasher, pkg.size).await?;
+        let mut offset = Some(len).filter(|len| *len > 0);
 
         // download
         let mut pb = ProgressBar::spawn()?;
-        let mut deb = Vec::new();
-        let mut hasher = Sha256::new();
-        let mut offset = None;
 
         let mut i: usize = 0;
-        loop {
+        let result = loop {
+            if pkg.size.is_some() && offset == pkg.size {
+                debug!("Partial download is already complete");
+                break Ok(());
+            }
+
             // increast the counter until usize::MAX, but do not overflow
             i = i.saturating_add(1);
             if max_download_attempts > 0 && i > max_download_attempts {
                 // number of download attempts exceeded
-                break;
+                break Err(anyhow!("Exceeded number of retries for download"));
             }
 
             if i > 1 {
@@ -156,29 +468,266 @@ impl Client {
             }
 
             if let Err(err) = self
-                .attempt_download(&url, &mut deb, &mut hasher, &mut pb, &mut offset)
+                .attempt_download(&url, &mut file, &mut hasher, &mut pb, &mut offset, pkg.size)
                 .await
             {
+                if err.is::<SizeLimitExceeded>() {
+                    break Err(err);
+                }
+                if err.is::<http::RangeNotSatisfiable>() {
+                    // this happens if the size is unknown and the previous run crashed before
+                    // the rename, the checksum verification below decides if it's actually complete
+                    debug!("Partial download is already complete: {err:#}");
+                    break Ok(());
+                }
                 warn!("Download has failed: {err:#}");
             } else {
-                pb.close().await?;
-
-                // verify checksum
-                info!("Verifying with sha256sum hash...");
-                let downloaded_sha256sum = crypto::sha256sum_hex(hasher);
-                if pkg.sha256sum != downloaded_sha256sum {
-                    bail!(
-                        "Downloaded bytes don't match signed sha256sum (signed: {:?}, downloaded: {:?})",
-                        pkg.sha256sum,
-                        downloaded_sha256sum
-                    );
-                }
+                break Ok(());
+            }
+        };
+        pb.close().await?;
+
+        if let Err(err) = result {
+            // a partial file that couldn't be completed would make every following update fail the same way
+            tokio::fs::remove_file(&part_path).await.ok();
+            return Err(err);
+        }
+
+        let size = file.metadata().await?.len();
+        drop(file);
+        if let Err(err) = verify_download(pkg, size, &hasher.finalize()) {
+            // don't attempt to resume from corrupted data
+            tokio::fs::remove_file(&part_path).await.ok();
+            return Err(err);
+        }
+
+        tokio::fs::rename(&part_path, &deb_path)
+            .await
+            .with_context(|| anyhow!("Failed to move verified download to {:?}", deb_path))?;
+        Ok(deb_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libflate::gzip::Encoder;
+    use std::io::Write;
+
+    const DATA: &[u8] = b"Package: spotify-client\nVersion: 1:1.1.84.716.gc5f8b819\n";
 
-                return Ok(deb);
+    struct FakeVerifier {
+        valid: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl pgp::Verifier for FakeVerifier {
+        async fn verify(&self, _sig: &[u8], _artifact: &[u8]) -> Result<pgp::SignatureInfo> {
+            if self.valid {
+                Ok(pgp::SignatureInfo::default())
+            } else {
+                bail!("Fake signature is invalid")
             }
         }
+    }
 
-        pb.close().await?;
-        bail!("Exceeded number of retries for download");
+    fn client(valid: bool) -> Result<Client> {
+        Client::new(
+            None,
+            "https://mirror.example.com/spotify/",
+            "stable",
+            deb::Architecture::Amd64,
+            Box::new(FakeVerifier { valid }),
+        )
+    }
+
+    #[test]
+    fn test_urls() -> Result<()> {
+        let client = client(true)?;
+        assert_eq!(
+            client.dists_url("InRelease"),
+            "https://mirror.example.com/spotify/dists/stable/InRelease"
+        );
+        let pkg = Pkg {
+            filename: "pool/non-free/s/spotify-client/spotify-client_1.2.13_amd64.deb".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            client.download_url(&pkg),
+            "https://mirror.example.com/spotify/pool/non-free/s/spotify-client/spotify-client_1.2.13_amd64.deb"
+        );
+        assert!(Client::new(
+            None,
+            "",
+            "stable",
+            deb::Architecture::Amd64,
+            Box::new(FakeVerifier { valid: true })
+        )
+        .is_err());
+        assert!(Client::new(
+            None,
+            "https://a",
+            "../x",
+            deb::Architecture::Amd64,
+            Box::new(FakeVerifier { valid: true })
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verifier() -> Result<()> {
+        client(true)?.verify_detached(DATA, b"sig").await?;
+        assert!(client(false)?.verify_detached(DATA, b"sig").await.is_err());
+        Ok(())
+    }
+
+    fn pkg(package: &str, version: &str) -> Pkg {
+        Pkg {
+            package: package.to_string(),
+            version: version.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_select_newest_spotify_client() -> Result<()> {
+        let index = PkgIndex {
+            release_date: None,
+            signature: Default::default(),
+            pkgs: vec![
+                pkg("spotify-client", "1:1.1.84.716.gc5f8b819"),
+                pkg("spotify-client", "1:1.2.13.661.ga588f749"),
+                pkg("spotify-client", "1:1.2.8.923.g4f94bf0d"),
+                pkg("spotify-client-gnome-support", "1:9.9.9"),
+                pkg("spotify-client", "not a version"),
+            ],
+        };
+        let pkg = index.spotify_client()?;
+        assert_eq!(pkg.version, "1:1.2.13.661.ga588f749");
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_pinned_spotify_client() -> Result<()> {
+        let index = PkgIndex {
+            release_date: None,
+            signature: Default::default(),
+            pkgs: vec![
+                pkg("spotify-client", "1:1.1.84.716.gc5f8b819"),
+                pkg("spotify-client", "1:1.2.13.661.ga588f749"),
+            ],
+        };
+        let pinned = "1:1.1.84.716.gc5f8b819".parse()?;
+        let pkg = index.select_spotify_client(Some(&pinned))?;
+        assert_eq!(pkg.version, "1:1.1.84.716.gc5f8b819");
+
+        let pkg = index.select_spotify_client(None)?;
+        assert_eq!(pkg.version, "1:1.2.13.661.ga588f749");
+
+        let pinned = "1:1.0.0".parse()?;
+        assert!(index.select_spotify_client(Some(&pinned)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_missing_spotify_client() {
+        let index = PkgIndex {
+            release_date: None,
+            signature: Default::default(),
+            pkgs: vec![pkg("spotify-client-gnome-support", "1:9.9.9")],
+        };
+        assert!(index.spotify_client().is_err());
+    }
+
+    #[test]
+    fn test_check_size() {
+        assert!(check_size(100, None).is_ok());
+        assert!(check_size(100, Some(100)).is_ok());
+        let err = check_size(101, Some(100)).unwrap_err();
+        assert!(err.is::<SizeLimitExceeded>());
+    }
+
+    #[test]
+    fn test_verify_download() -> Result<()> {
+        let mut hasher = crypto::Hasher::default();
+        hasher.update(b"ohai!");
+        let hashes = hasher.finalize();
+
+        let mut pkg = Pkg {
+            size: Some(5),
+            sha256sum: hashes.sha256.clone(),
+            sha512sum: Some(hashes.sha512.clone()),
+            ..Default::default()
+        };
+        verify_download(&pkg, 5, &hashes)?;
+        assert!(verify_download(&pkg, 6, &hashes).is_err());
+
+        pkg.sha512sum = Some("00".repeat(64));
+        assert!(verify_download(&pkg, 5, &hashes).is_err());
+
+        pkg.sha512sum = None;
+        pkg.size = None;
+        verify_download(&pkg, 5, &hashes)?;
+
+        pkg.sha256sum = "00".repeat(32);
+        assert!(verify_download(&pkg, 5, &hashes).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resume_partial_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("partial.deb.part");
+
+        let mut hasher = crypto::Hasher::default();
+        let (_, len) = Client::resume_partial_file(&path, &mut hasher, Some(5)).await?;
+        assert_eq!(len, 0);
+
+        std::fs::write(&path, b"oh")?;
+        let mut hasher = crypto::Hasher::default();
+        let (mut file, len) = Client::resume_partial_file(&path, &mut hasher, Some(5)).await?;
+        assert_eq!(len, 2);
+        file.write_all(b"ai!").await?;
+        file.flush().await?;
+        hasher.update(b"ai!");
+        drop(file);
+        assert_eq!(std::fs::read(&path)?, b"ohai!");
+        assert_eq!(hasher.finalize().sha256, crypto::sha256sum(b"ohai!"));
+
+        // partial file larger than the signed size is discarded
+        let mut hasher = crypto::Hasher::default();
+        let (_, len) = Client::resume_partial_file(&path, &mut hasher, Some(4)).await?;
+        assert_eq!(len, 0);
+        assert_eq!(std::fs::read(&path)?, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_xz() -> Result<()> {
+        let compressed = lzma::compress(DATA, 6)?;
+        assert_eq!(Compression::Xz.decompress(&compressed)?, DATA);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_gzip() -> Result<()> {
+        let mut encoder = Encoder::new(Vec::new())?;
+        encoder.write_all(DATA)?;
+        let compressed = encoder.finish().into_result()?;
+        assert_eq!(Compression::Gzip.decompress(&compressed)?, DATA);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_none() -> Result<()> {
+        assert_eq!(Compression::None.decompress(DATA)?, DATA);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        assert!(Compression::Gzip.decompress(DATA).is_err());
+        assert!(Compression::Xz.decompress(DATA).is_err());
     }
 }
@@ -1,41 +1,203 @@
+use crate::deb;
+use crate::pgp;
 use std::path::PathBuf;
 
 #[derive(Debug, clap::Parser)]
 #[command(version)]
 pub struct Args {
-    /// Overwrite the default keyring
-    #[arg(long, default_value = "/usr/share/spotify-launcher/keyring.pgp")]
+    /// Overwrite the default keyring, this can also be a directory of .gpg/.asc/.pgp keys
+    #[arg(
+        long,
+        global = true,
+        default_value = "/usr/share/spotify-launcher/keyring.pgp"
+    )]
     pub keyring: PathBuf,
-    /// Use a local .deb file instead of downloading one
-    #[arg(long)]
-    pub deb: Option<PathBuf>,
     /// Install into specific directory
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub install_dir: Option<PathBuf>,
     /// Verbose logs (can be used multiple times)
-    #[arg(short, long, action = clap::ArgAction::Count)]
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
     pub verbose: u8,
+    /// The timeout to use for http connections and requests
+    #[arg(long, global = true)]
+    pub timeout: Option<u64>,
+    /// How often to try to resume the download until giving up (0 for unlimited)
+    #[arg(long, global = true)]
+    pub download_attempts: Option<usize>,
+    /// Base url of the apt repository to download spotify from
+    #[arg(long, global = true)]
+    pub repository: Option<String>,
+    /// The suite of the apt repository to use (e.g. `testing` or `stable`)
+    #[arg(long, global = true)]
+    pub suite: Option<String>,
+    /// Which implementation to use for pgp signature verification
+    #[arg(long, global = true, value_enum)]
+    pub pgp_backend: Option<pgp::Backend>,
+    /// Download packages for this debian architecture instead of the current cpu's (e.g. `arm64`)
+    #[arg(long, global = true)]
+    pub arch: Option<deb::Architecture>,
+    /// Install this exact spotify version instead of the latest one (same as `pinned_version` in the config)
+    #[arg(long, global = true)]
+    pub pin_version: Option<String>,
+    /// Arguments of the `run` command, used if no subcommand is given
+    #[command(flatten)]
+    pub run: Run,
+    #[command(subcommand)]
+    pub subcommand: Option<SubCommand>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum SubCommand {
+    /// Update spotify if needed and start it (this is the default)
+    ///
+    /// Update errors are displayed and spotify is started anyway, this only fails if spotify can't be started.
+    Run(Run),
+    /// Check for a new version and install it, without starting spotify
+    ///
+    /// Exits successfully if the latest version is installed afterwards, or with an error if the update failed.
+    Update(Update),
+    /// Install spotify if it isn't installed yet, without starting it
+    ///
+    /// Does nothing if spotify is already installed, unless `--deb` or `--force` is used.
+    Install(Install),
+    /// Show the installed version, update state and whether the install looks healthy
+    ///
+    /// Exits with an error if spotify isn't installed or its binary is missing.
+    Status(Status),
+    /// Remove all installed versions, the state file and the download cache
+    ///
+    /// Lists everything that is going to be deleted and asks for confirmation first.
+    Uninstall(Uninstall),
+    /// Check for the latest .deb and print its url
+    PrintDebUrl,
+    /// Check the environment for common problems and print a report
+    ///
+    /// Exits with an error if any check failed.
+    Doctor,
+    /// List all spotify versions available in the repository
+    ListVersions(ListVersions),
+    /// Manage the cache of downloaded .deb files
+    Cache(Cache),
+    /// Check the installed files against the checksums of the package
+    Verify(Verify),
+    /// Switch back to the version that was active before the last update
+    Rollback,
+}
+
+#[derive(Debug, Default, PartialEq, clap::Parser)]
+pub struct Run {
     /// Uri to pass to the spotify child process
     pub uri: Option<String>,
+    /// Use a local .deb file instead of downloading one
+    #[arg(long)]
+    pub deb: Option<PathBuf>,
     /// Always check for updates when starting
     #[arg(long)]
     pub check_update: bool,
     /// Never check for updates when starting
-    #[arg(long)]
+    #[arg(long, conflicts_with_all = ["check_update", "force_update", "deb"])]
     pub skip_update: bool,
     /// Update even if latest version is already installed
     #[arg(long)]
     pub force_update: bool,
-    /// Check for the latest .deb and print its url
-    #[arg(long)]
-    pub print_deb_url: bool,
     /// Run the install/update code but don't actually run the final binary
     #[arg(long)]
     pub no_exec: bool,
-    /// The timeout to use for http connections and requests
+    /// Watch the first start after an update and roll back if spotify crashes right away
     #[arg(long)]
-    pub timeout: Option<u64>,
-    /// How often to try to resume the download until giving up (0 for unlimited)
+    pub supervise: bool,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct Update {
+    /// Reinstall even if the latest version is already installed, or downgrade if needed
     #[arg(long)]
-    pub download_attempts: Option<usize>,
+    pub force: bool,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct Install {
+    /// Install a local .deb file instead of downloading one
+    #[arg(long)]
+    pub deb: Option<PathBuf>,
+    /// Reinstall even if spotify is already installed
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct Status {
+    /// Print the status as json
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct Uninstall {
+    /// Also remove spotify's configuration, this logs you out
+    #[arg(long)]
+    pub user_config: bool,
+    /// Also remove spotify's cache, e.g. downloaded songs
+    #[arg(long)]
+    pub user_cache: bool,
+    /// Only list what would be removed
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Don't ask for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct ListVersions {
+    /// Print the list of versions as json
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct Verify {
+    /// Reinstall spotify if any files are missing or modified
+    #[arg(long)]
+    pub repair: bool,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct Cache {
+    #[command(subcommand)]
+    pub subcommand: CacheCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum CacheCommand {
+    /// Delete all cached .deb files
+    Clean,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, Parser};
+
+    #[test]
+    fn test_cli() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_global_options_before_subcommand() {
+        let args = Args::parse_from(["spotify-launcher", "-v", "--keyring", "/tmp/x", "status"]);
+        assert_eq!(args.verbose, 1);
+        assert_eq!(args.keyring, PathBuf::from("/tmp/x"));
+        assert!(matches!(args.subcommand, Some(SubCommand::Status(_))));
+        assert_eq!(args.run, Run::default());
+
+        let args = Args::parse_from(["spotify-launcher", "-v", "doctor"]);
+        assert!(matches!(args.subcommand, Some(SubCommand::Doctor)));
+
+        let args = Args::parse_from(["spotify-launcher", "--skip-update", "spotify:track:1"]);
+        assert!(args.subcommand.is_none());
+        assert!(args.run.skip_update);
+        assert_eq!(args.run.uri.as_deref(), Some("spotify:track:1"));
+    }
 }
@@ -0,0 +1,261 @@
+use crate::apt;
+use crate::crypto;
+use crate::deb::Pkg;
+use crate::errors::*;
+use crate::paths;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::fs;
+
+pub const DEFAULT_MAX_VERSIONS: usize = 2;
+
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// Verified .deb files, keyed by their sha256
+pub struct Cache {
+    path: PathBuf,
+}
+
+impl Cache {
+    pub fn new(path: PathBuf) -> Cache {
+        Cache { path }
+    }
+
+    pub fn open() -> Result<Cache> {
+        Ok(Cache::new(paths::cache_path()?))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn validate_sha256(sha256: &str) -> Result<()> {
+        // the hash is used as filename, it must not be able to point outside of the cache
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("Invalid sha256sum in package index: {:?}", sha256);
+        }
+        Ok(())
+    }
+
+    pub fn entry_path(&self, sha256: &str) -> Result<PathBuf> {
+        Self::validate_sha256(sha256)?;
+        Ok(self.path.join(format!("{sha256}.deb")))
+    }
+
+    pub fn part_path(&self, sha256: &str) -> Result<PathBuf> {
+        Self::validate_sha256(sha256)?;
+        Ok(self.path.join(format!("{sha256}.deb.part")))
+    }
+
+    pub async fn create_dir(&self) -> Result<()> {
+        fs::create_dir_all(&self.path)
+            .await
+            .with_context(|| anyhow!("Failed to create cache directory {:?}", self.path))
+    }
+
+    /// Return the path of a cached .deb if it still matches the signed package index
+    pub async fn lookup(&self, pkg: &Pkg) -> Result<Option<PathBuf>> {
+        let path = self.entry_path(&pkg.sha256sum)?;
+        if fs::metadata(&path).await.is_err() {
+            debug!("No cached .deb for {:?} at {:?}", pkg.version, path);
+            return Ok(None);
+        }
+
+        info!("Verifying cached .deb at {:?}...", path);
+        let (size, hashes) = hash_file(&path).await?;
+        if let Err(err) = apt::verify_download(pkg, size, &hashes) {
+            warn!("Discarding cached .deb at {:?}: {:#}", path, err);
+            fs::remove_file(&path)
+                .await
+                .with_context(|| anyhow!("Failed to delete cached .deb at {:?}", path))?;
+            return Ok(None);
+        }
+
+        // mark as recently used so it's evicted last
+        let file = std::fs::File::options().append(true).open(&path)?;
+        file.set_modified(SystemTime::now())?;
+
+        Ok(Some(path))
+    }
+
+    /// List all complete entries, most recently used first
+    pub async fn entries(&self) -> Result<Vec<Entry>> {
+        let mut out = Vec::new();
+        let mut dir = match fs::read_dir(&self.path).await {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(out),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| anyhow!("Failed to read cache directory {:?}", self.path))
+            }
+        };
+
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("deb") {
+                continue;
+            }
+            let metadata = entry.metadata().await?;
+            out.push(Entry {
+                path,
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            });
+        }
+
+        out.sort_by_key(|e| std::cmp::Reverse(e.modified));
+        Ok(out)
+    }
+
+    /// Delete the least recently used entries until the cache fits into the given limits
+    pub async fn evict(&self, max_versions: usize, max_size: Option<u64>) -> Result<()> {
+        let mut total = 0;
+        for (i, entry) in self.entries().await?.into_iter().enumerate() {
+            total += entry.size;
+            let over_size = max_size.is_some_and(|max_size| total > max_size);
+            if i >= max_versions || over_size {
+                info!("Evicting {:?} from cache", entry.path);
+                fs::remove_file(&entry.path)
+                    .await
+                    .with_context(|| anyhow!("Failed to delete cached .deb at {:?}", entry.path))?;
+                total -= entry.size;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete all entries, including incomplete downloads. Returns the number of bytes freed.
+    pub async fn clean(&self) -> Result<u64> {
+        let mut freed = 0;
+        let mut dir = match fs::read_dir(&self.path).await {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| anyhow!("Failed to read cache directory {:?}", self.path))
+            }
+        };
+
+        while let Some(entry) = dir.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+            debug!("Removing {:?} from cache", entry.path());
+            fs::remove_file(entry.path())
+                .await
+                .with_context(|| anyhow!("Failed to delete {:?}", entry.path()))?;
+            freed += metadata.len();
+        }
+        Ok(freed)
+    }
+}
+
+pub async fn hash_file(path: &Path) -> Result<(u64, crypto::Hashes)> {
+    let mut file = fs::File::open(path)
+        .await
+        .with_context(|| anyhow!("Failed to open {:?}", path))?;
+    let mut hasher = crypto::Hasher::default();
+    let size = crypto::hash_reader(&mut file, |bytes| hasher.update(bytes))
+        .await
+        .with_context(|| anyhow!("Failed to read {:?}", path))?;
+    Ok((size, hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn add_entry(cache: &Cache, data: &[u8], age: u64) -> Result<PathBuf> {
+        let path = cache.entry_path(&crypto::sha256sum(data))?;
+        fs::write(&path, data).await?;
+        let file = std::fs::File::options().append(true).open(&path)?;
+        file.set_modified(SystemTime::now() - Duration::from_secs(age))?;
+        Ok(path)
+    }
+
+    #[tokio::test]
+    async fn test_lookup() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new(dir.path().to_path_buf());
+
+        let mut hasher = crypto::Hasher::default();
+        hasher.update(b"ohai!");
+        let hashes = hasher.finalize();
+        let pkg = Pkg {
+            size: Some(5),
+            sha256sum: hashes.sha256.clone(),
+            sha512sum: Some(hashes.sha512.clone()),
+            ..Default::default()
+        };
+        assert_eq!(cache.lookup(&pkg).await?, None);
+
+        let path = add_entry(&cache, b"ohai!", 0).await?;
+        assert_eq!(cache.lookup(&pkg).await?, Some(path.clone()));
+
+        // corrupted entries are removed
+        fs::write(&path, b"ohno!").await?;
+        assert_eq!(cache.lookup(&pkg).await?, None);
+        assert!(fs::metadata(&path).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_hash() {
+        let cache = Cache::new(PathBuf::from("/nonexistent"));
+        assert!(cache.entry_path("../../etc/passwd").is_err());
+        assert!(cache.entry_path(&"a".repeat(63)).is_err());
+        assert!(cache.part_path(&"g".repeat(64)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_evict_by_count() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new(dir.path().to_path_buf());
+        let a = add_entry(&cache, b"a", 300).await?;
+        let b = add_entry(&cache, b"b", 100).await?;
+        let c = add_entry(&cache, b"c", 200).await?;
+
+        cache.evict(2, None).await?;
+        let entries = cache.entries().await?;
+        let paths = entries.into_iter().map(|e| e.path).collect::<Vec<_>>();
+        assert_eq!(paths, vec![b, c]);
+        assert!(fs::metadata(&a).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_evict_by_size() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new(dir.path().to_path_buf());
+        let a = add_entry(&cache, b"aaaa", 100).await?;
+        add_entry(&cache, b"bbbb", 200).await?;
+        add_entry(&cache, b"cccc", 300).await?;
+
+        cache.evict(10, Some(6)).await?;
+        let entries = cache.entries().await?;
+        let paths = entries.into_iter().map(|e| e.path).collect::<Vec<_>>();
+        assert_eq!(paths, vec![a]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clean() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new(dir.path().join("cache"));
+        assert_eq!(cache.clean().await?, 0);
+
+        cache.create_dir().await?;
+        add_entry(&cache, b"aaaa", 100).await?;
+        fs::write(cache.part_path(&"0".repeat(64))?, b"bb").await?;
+        assert_eq!(cache.clean().await?, 6);
+        assert_eq!(cache.entries().await?, vec![]);
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::errors::*;
+use crate::pgp;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -54,6 +55,17 @@ pub struct SpotifyConfig {
     #[serde(default)]
     pub extra_env_vars: Vec<String>,
     pub download_attempts: Option<usize>,
+    pub repository: Option<String>,
+    pub suite: Option<String>,
+    pub pinned_version: Option<String>,
+    pub cache_max_versions: Option<usize>,
+    pub cache_max_size: Option<u64>,
+    pub install_max_versions: Option<usize>,
+    #[serde(default)]
+    pub supervise_updates: bool,
+    pub supervise_window: Option<u64>,
+    #[serde(default)]
+    pub pgp_backend: pgp::Backend,
 }
 
 #[cfg(test)]
@@ -73,4 +85,64 @@ mod tests {
         assert_eq!(cf, ConfigFile::default());
         Ok(())
     }
+
+    #[test]
+    fn test_repository_config() -> Result<()> {
+        let cf = ConfigFile::parse(
+            r#"[spotify]
+repository = "https://mirror.example.com/spotify"
+suite = "stable"
+"#,
+        )?;
+        assert_eq!(
+            cf.spotify.repository.as_deref(),
+            Some("https://mirror.example.com/spotify")
+        );
+        assert_eq!(cf.spotify.suite.as_deref(), Some("stable"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pgp_backend_config() -> Result<()> {
+        let cf = ConfigFile::parse("[spotify]\npgp_backend = \"native\"\n")?;
+        assert_eq!(cf.spotify.pgp_backend, pgp::Backend::Native);
+        let cf = ConfigFile::parse("[spotify]\npgp_backend = \"sqv\"\n")?;
+        assert_eq!(cf.spotify.pgp_backend, pgp::Backend::Sqv);
+        assert!(ConfigFile::parse("[spotify]\npgp_backend = \"gpg\"\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_version_config() -> Result<()> {
+        let cf = ConfigFile::parse(
+            r#"[spotify]
+pinned_version = "1:1.2.13.661.ga588f749"
+"#,
+        )?;
+        assert_eq!(
+            cf.spotify.pinned_version.as_deref(),
+            Some("1:1.2.13.661.ga588f749")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_install_max_versions_config() -> Result<()> {
+        let cf = ConfigFile::parse("[spotify]\ninstall_max_versions = 3\n")?;
+        assert_eq!(cf.spotify.install_max_versions, Some(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_supervise_config() -> Result<()> {
+        let cf = ConfigFile::parse(
+            r#"[spotify]
+supervise_updates = true
+supervise_window = 60
+"#,
+        )?;
+        assert!(cf.spotify.supervise_updates);
+        assert_eq!(cf.spotify.supervise_window, Some(60));
+        Ok(())
+    }
 }
@@ -1,5 +1,7 @@
 use base16ct::HexDisplay;
-use sha2::{Digest, Sha256};
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 pub fn sha256sum(bytes: &[u8]) -> String {
     let mut hasher = Sha256::new();
@@ -11,6 +13,60 @@ pub fn sha256sum_hex(hasher: Sha256) -> String {
     format!("{:x}", HexDisplay(&hasher.finalize()))
 }
 
+pub fn sha512sum_hex(hasher: Sha512) -> String {
+    format!("{:x}", HexDisplay(&hasher.finalize()))
+}
+
+/// md5 is only used to detect corruption of installed files, not for authentication
+pub fn md5sum_hex(hasher: Md5) -> String {
+    format!("{:x}", HexDisplay(&hasher.finalize()))
+}
+
+/// Feed everything that can be read from `reader` into a hasher, returns the number of bytes read
+pub async fn hash_reader<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    mut update: impl FnMut(&[u8]),
+) -> std::io::Result<u64> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok(size)
+}
+
+/// Calculate all hashes we may need to verify a download in a single pass
+#[derive(Default)]
+pub struct Hasher {
+    sha256: Sha256,
+    sha512: Sha512,
+}
+
+impl Hasher {
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.sha256.update(bytes);
+        self.sha512.update(bytes);
+    }
+
+    pub fn finalize(self) -> Hashes {
+        Hashes {
+            sha256: sha256sum_hex(self.sha256),
+            sha512: sha512sum_hex(self.sha512),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Hashes {
+    pub sha256: String,
+    pub sha512: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -23,4 +79,37 @@ mod tests {
             "f66b9e95324778cbc291d16cc30a950a0cacfe1c06e72cd9743d474c5e3e6b99"
         );
     }
+
+    #[test]
+    fn test_md5() {
+        let mut hasher = Md5::new();
+        hasher.update(b"ohai!");
+        assert_eq!(md5sum_hex(hasher), "36252c01906b7993eabdc504fdf8af96");
+    }
+
+    #[test]
+    fn test_hasher() {
+        let mut hasher = Hasher::default();
+        hasher.update(b"oh");
+        hasher.update(b"ai!");
+        let hashes = hasher.finalize();
+        assert_eq!(
+            hashes.sha256,
+            "f66b9e95324778cbc291d16cc30a950a0cacfe1c06e72cd9743d474c5e3e6b99"
+        );
+        let mut sha512 = Sha512::new();
+        sha512.update(b"ohai!");
+        assert_eq!(hashes.sha512, sha512sum_hex(sha512));
+        assert_eq!(hashes.sha512.len(), 128);
+    }
+
+    #[tokio::test]
+    async fn test_hash_reader() -> std::io::Result<()> {
+        let data = vec![0x42; 200 * 1024];
+        let mut hasher = Sha256::new();
+        let size = hash_reader(&mut &data[..], |bytes| hasher.update(bytes)).await?;
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(sha256sum_hex(hasher), sha256sum(&data));
+        Ok(())
+    }
 }
@@ -1,144 +1,578 @@
-use crate::apt::SPOTIFY_REPOSITORY;
+use crate::deb822::{self, Paragraph};
 use crate::errors::*;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Architecture {
     Amd64,
     I386,
-    Unknown(&'static str),
+    Arm64,
+    Armhf,
+    Armel,
+    Riscv64,
+    Ppc64el,
+    S390x,
+    Unknown(String),
 }
 
 impl Architecture {
-    pub const fn current() -> Architecture {
+    pub fn current() -> Architecture {
         if cfg!(target_arch = "x86_64") {
             Architecture::Amd64
         } else if cfg!(target_arch = "x86") {
             Architecture::I386
+        } else if cfg!(target_arch = "aarch64") {
+            Architecture::Arm64
+        } else if cfg!(all(target_arch = "arm", target_abi = "eabihf")) {
+            Architecture::Armhf
+        } else if cfg!(target_arch = "arm") {
+            Architecture::Armel
+        } else if cfg!(target_arch = "riscv64") {
+            Architecture::Riscv64
+        } else if cfg!(all(target_arch = "powerpc64", target_endian = "little")) {
+            Architecture::Ppc64el
+        } else if cfg!(target_arch = "s390x") {
+            Architecture::S390x
         } else {
-            Architecture::Unknown(env::consts::ARCH)
+            Architecture::Unknown(env::consts::ARCH.to_string())
         }
     }
 
-    pub const fn to_debian_str(&self) -> &str {
+    pub fn to_debian_str(&self) -> &str {
         match self {
             Architecture::Amd64 => "amd64",
             Architecture::I386 => "i386",
+            Architecture::Arm64 => "arm64",
+            Architecture::Armhf => "armhf",
+            Architecture::Armel => "armel",
+            Architecture::Riscv64 => "riscv64",
+            Architecture::Ppc64el => "ppc64el",
+            Architecture::S390x => "s390x",
             Architecture::Unknown(arch) => arch,
         }
     }
 }
 
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_debian_str())
+    }
+}
+
+impl FromStr for Architecture {
+    type Err = Error;
+
+    /// Parse a debian architecture name, unknown names are accepted so other repositories can be used
+    fn from_str(s: &str) -> Result<Self> {
+        let arch = match s {
+            "amd64" => Architecture::Amd64,
+            "i386" => Architecture::I386,
+            "arm64" => Architecture::Arm64,
+            "armhf" => Architecture::Armhf,
+            "armel" => Architecture::Armel,
+            "riscv64" => Architecture::Riscv64,
+            "ppc64el" => Architecture::Ppc64el,
+            "s390x" => Architecture::S390x,
+            // unknown names end up in the `binary-<arch>` path of the index url, keep them to what dpkg allows
+            _ if !s.is_empty()
+                && s.bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-') =>
+            {
+                Architecture::Unknown(s.to_string())
+            }
+            _ => bail!("Invalid debian architecture: {:?}", s),
+        };
+        Ok(arch)
+    }
+}
+
 #[derive(Debug, PartialEq, Default)]
 pub struct Release {
     pub architectures: Vec<String>,
+    pub date: Option<SystemTime>,
+    pub valid_until: Option<SystemTime>,
     pub sha256_sums: HashMap<String, String>,
 }
 
-pub fn parse_release_file(data: &str) -> Result<Release> {
-    let mut section = None;
-    let mut release = Release::default();
+impl Release {
+    /// Make sure this release file is neither expired nor older than a release we already accepted
+    pub fn verify_freshness(
+        &self,
+        now: SystemTime,
+        last_release_date: Option<SystemTime>,
+    ) -> Result<()> {
+        if let Some(valid_until) = self.valid_until {
+            if valid_until < now {
+                bail!(
+                    "Release file has expired (valid until: {})",
+                    format_date(valid_until)
+                );
+            }
+        }
 
-    for line in data.lines() {
-        trace!("Release file parser got line: {:?}", line);
-
-        if let Some(line) = line.strip_prefix(' ') {
-            if section == Some("SHA256:") {
-                let (hash, line) = line
-                    .split_once(' ')
-                    .context("Malformed sha256 line in release file")?;
-                let (_, file) = line
-                    .rsplit_once(' ')
-                    .context("Malformed sha256 line in release file")?;
-
-                debug!(
-                    "Adding file hash from release file, {:?} => {:?}",
-                    file, hash
+        if let Some(last_release_date) = last_release_date {
+            let Some(date) = self.date else {
+                bail!("Release file is missing a `Date` field");
+            };
+            if date < last_release_date {
+                bail!(
+                    "Release file is older than the one we've previously seen (date: {}, previously seen: {})",
+                    format_date(date),
+                    format_date(last_release_date)
                 );
-                release
-                    .sha256_sums
-                    .insert(file.to_string(), hash.to_string());
             }
-        } else if line.ends_with(':') {
-            section = Some(line);
-        } else if let Some((key, value)) = line.split_once(": ") {
-            if key == "Architectures" {
-                let list = value.split(' ').map(String::from);
-                release.architectures = list.collect();
+        }
+
+        Ok(())
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// number of days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Parse dates as used in apt release files, e.g. `Wed, 27 Apr 2022 12:30:15 UTC`
+/// Parse an unsigned number, without the leading `+` that `u32::from_str` would accept
+fn parse_digits(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+pub fn parse_date(s: &str) -> Result<SystemTime> {
+    let invalid = || anyhow!("Invalid date in release file: {:?}", s);
+
+    let date = s.split_once(", ").map(|(_, date)| date).unwrap_or(s);
+    let mut parts = date.split_whitespace();
+    let (Some(day), Some(month), Some(year), Some(time), Some(zone), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return Err(invalid());
+    };
+
+    let day = day.parse::<i64>().map_err(|_| invalid())?;
+    let month = MONTHS
+        .iter()
+        .position(|m| *m == month)
+        .ok_or_else(invalid)? as i64
+        + 1;
+    let year = year.parse::<i64>().map_err(|_| invalid())?;
+    if !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+
+    let mut time = time.split(':').map(parse_digits);
+    let (Some(Some(hour)), Some(Some(minute)), Some(Some(second)), None) =
+        (time.next(), time.next(), time.next(), time.next())
+    else {
+        return Err(invalid());
+    };
+    // 60 is a leap second
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid());
+    }
+    let (hour, minute, second) = (i64::from(hour), i64::from(minute), i64::from(second));
+
+    let offset = match zone {
+        "UTC" | "GMT" | "Z" => 0,
+        zone => {
+            let (sign, digits) = match zone.split_at_checked(1) {
+                Some(("+", digits)) => (1, digits),
+                Some(("-", digits)) => (-1, digits),
+                _ => return Err(invalid()),
+            };
+            if digits.len() != 4 {
+                return Err(invalid());
             }
+            let digits = i64::from(parse_digits(digits).ok_or_else(invalid)?);
+            sign * ((digits / 100) * 3600 + (digits % 100) * 60)
+        }
+    };
+
+    let secs =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let secs = u64::try_from(secs).map_err(|_| invalid())?;
+    Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Format a timestamp the way it's used in apt release files
+pub fn format_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let days = secs.div_euclid(86400);
+    let secs = secs.rem_euclid(86400);
+
+    // inverse of days_from_civil
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:02} {} {} {:02}:{:02}:{:02} UTC",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+pub fn parse_release_file(data: &str) -> Result<Release> {
+    let paragraphs = deb822::parse(data).context("Failed to parse release file")?;
+    let [paragraph] = &paragraphs[..] else {
+        bail!(
+            "Expected exactly one paragraph in release file, found {}",
+            paragraphs.len()
+        );
+    };
+
+    let mut release = Release::default();
+
+    if let Some(value) = paragraph.get("Architectures") {
+        let list = value.split_whitespace().map(String::from);
+        release.architectures = list.collect();
+    }
+    if let Some(value) = paragraph.get("Date") {
+        release.date = Some(parse_date(value)?);
+    }
+    if let Some(value) = paragraph.get("Valid-Until") {
+        release.valid_until = Some(parse_date(value)?);
+    }
+
+    if let Some(value) = paragraph.get("SHA256") {
+        for line in value.lines().filter(|l| !l.trim().is_empty()) {
+            let mut parts = line.split_whitespace();
+            let (Some(hash), Some(_size), Some(file), None) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                bail!("Malformed sha256 line in release file: {:?}", line);
+            };
+
+            debug!(
+                "Adding file hash from release file, {:?} => {:?}",
+                file, hash
+            );
+            release
+                .sha256_sums
+                .insert(file.to_string(), hash.to_string());
         }
     }
 
     Ok(release)
 }
 
-#[derive(Debug, PartialEq)]
+/// A debian package version, compared according to the rules of dpkg
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub epoch: u64,
+    pub upstream: String,
+    pub revision: String,
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Version> {
+        let s = s.trim();
+        let (epoch, rest) = match s.split_once(':') {
+            Some((epoch, rest)) => {
+                let epoch = epoch
+                    .parse()
+                    .with_context(|| anyhow!("Invalid epoch in version: {:?}", s))?;
+                (epoch, rest)
+            }
+            None => (0, s),
+        };
+        let (upstream, revision) = rest.rsplit_once('-').unwrap_or((rest, ""));
+
+        if upstream.is_empty() {
+            bail!("Version has empty upstream version: {:?}", s);
+        }
+        let is_valid = |c: char| c.is_ascii_alphanumeric() || ".+-~:".contains(c);
+        if !upstream.chars().all(is_valid) || !revision.chars().all(is_valid) {
+            bail!("Version contains invalid characters: {:?}", s);
+        }
+
+        Ok(Version {
+            epoch,
+            upstream: upstream.to_string(),
+            revision: revision.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.epoch != 0 {
+            write!(f, "{}:", self.epoch)?;
+        }
+        write!(f, "{}", self.upstream)?;
+        if !self.revision.is_empty() {
+            write!(f, "-{}", self.revision)?;
+        }
+        Ok(())
+    }
+}
+
+// sort order of a non-digit character, `~` sorts before everything, even the end of the string
+fn char_order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(b'~') => -1,
+        Some(c) if c.is_ascii_alphabetic() => i32::from(c),
+        Some(c) => i32::from(c) + 256,
+    }
+}
+
+// port of `verrevcmp` from dpkg
+fn compare_part(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+
+    while !a.is_empty() || !b.is_empty() {
+        // compare the non-digit prefix
+        while a.first().is_some_and(|c| !c.is_ascii_digit())
+            || b.first().is_some_and(|c| !c.is_ascii_digit())
+        {
+            let ac = char_order(a.first().copied().filter(|c| !c.is_ascii_digit()));
+            let bc = char_order(b.first().copied().filter(|c| !c.is_ascii_digit()));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            a = a.get(1..).unwrap_or_default();
+            b = b.get(1..).unwrap_or_default();
+        }
+
+        // compare the numeric part
+        let skip_zeros = |s: &[u8]| {
+            let zeros = s.iter().take_while(|c| **c == b'0').count();
+            s[zeros..].to_vec()
+        };
+        let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+
+        let an = skip_zeros(&a[..digits(a)]);
+        let bn = skip_zeros(&b[..digits(b)]);
+        let ord = an.len().cmp(&bn.len()).then_with(|| an.cmp(&bn));
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        a = &a[digits(a)..];
+        b = &b[digits(b)..];
+    }
+
+    Ordering::Equal
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| compare_part(&self.upstream, &other.upstream))
+            .then_with(|| compare_part(&self.revision, &other.revision))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+#[derive(Debug, PartialEq, Default, Clone)]
 pub struct Pkg {
     pub package: String,
     pub version: String,
+    pub architecture: Option<String>,
     pub filename: String,
+    pub size: Option<u64>,
+    pub installed_size: Option<u64>,
     pub sha256sum: String,
+    pub sha512sum: Option<String>,
+    pub depends: Option<String>,
+    pub recommends: Option<String>,
+    pub homepage: Option<String>,
+    pub description: Option<String>,
 }
 
 impl Pkg {
-    pub fn download_url(&self) -> String {
-        format!("{SPOTIFY_REPOSITORY}/{}", self.filename)
+    pub fn from_paragraph(paragraph: &Paragraph) -> Result<Pkg> {
+        let required = |key: &str| {
+            paragraph
+                .get(key)
+                .map(String::from)
+                .with_context(|| anyhow!("Missing field: `{}`", key))
+        };
+        let optional = |key: &str| paragraph.get(key).map(String::from);
+        let number = |key: &str| {
+            paragraph
+                .get(key)
+                .map(|value| {
+                    value
+                        .parse::<u64>()
+                        .with_context(|| anyhow!("Invalid number in field `{}`: {:?}", key, value))
+                })
+                .transpose()
+        };
+
+        Ok(Pkg {
+            package: required("Package")?,
+            version: required("Version")?,
+            architecture: optional("Architecture"),
+            filename: required("Filename")?,
+            size: number("Size")?,
+            installed_size: number("Installed-Size")?,
+            sha256sum: required("SHA256")?,
+            sha512sum: optional("SHA512"),
+            depends: optional("Depends"),
+            recommends: optional("Recommends"),
+            homepage: optional("Homepage"),
+            description: optional("Description"),
+        })
     }
 }
 
-#[derive(Debug, PartialEq, Default)]
-pub struct NewPkg {
-    package: Option<String>,
-    version: Option<String>,
-    filename: Option<String>,
-    sha256sum: Option<String>,
+pub fn parse_package_index(data: &str) -> Result<Vec<Pkg>> {
+    let paragraphs = deb822::parse(data).context("Failed to parse package index")?;
+    paragraphs.iter().map(Pkg::from_paragraph).collect()
 }
 
-impl NewPkg {
-    pub fn set(&mut self, key: &str, value: String) {
-        match key {
-            "Package" => self.package = Some(value),
-            "Version" => self.version = Some(value),
-            "Filename" => self.filename = Some(value),
-            "SHA256" => self.sha256sum = Some(value),
-            _ => (),
-        }
-    }
+/// An entry of the `md5sums` file inside a .deb
+#[derive(Debug, PartialEq, Clone)]
+pub struct Md5Sum {
+    pub md5sum: String,
+    /// Path of the file, relative to the install directory
+    pub path: PathBuf,
 }
 
-pub fn parse_package_index(data: &str) -> Result<Vec<Pkg>> {
+pub fn parse_md5sums(data: &str) -> Result<Vec<Md5Sum>> {
     let mut out = Vec::new();
-    let mut pkg: Option<NewPkg> = None;
-
-    for line in data.lines().chain([""]) {
-        trace!("Package index parser got line: {:?}", line);
-
-        if line.is_empty() {
-            if let Some(pkg) = pkg.take() {
-                out.push(Pkg {
-                    package: pkg.package.context("Missing field: `package`")?,
-                    version: pkg.version.context("Missing field: `version`")?,
-                    filename: pkg.filename.context("Missing field: `filename`")?,
-                    sha256sum: pkg.sha256sum.context("Missing field: `sha256sum`")?,
-                });
-            }
-        } else {
-            if line.starts_with(' ') || line.ends_with(':') {
-                // not supported
-                continue;
-            }
+    for line in data.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (md5sum, path) = line
+            .split_once(' ')
+            .with_context(|| anyhow!("Invalid line in md5sums file: {:?}", line))?;
+        if md5sum.len() != 32 || !md5sum.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("Invalid md5sum in md5sums file: {:?}", md5sum);
+        }
 
-            let (key, value) = line
-                .split_once(": ")
-                .with_context(|| anyhow!("Line does not have key-value format: {:?}", line))?;
+        let path = Path::new(path.trim_start());
+        // the path is joined with the install directory, make sure it can't point somewhere else
+        if path.as_os_str().is_empty()
+            || !path.components().all(|c| matches!(c, Component::Normal(_)))
+        {
+            bail!("Invalid path in md5sums file: {:?}", path);
+        }
 
-            pkg.get_or_insert_with(NewPkg::default)
-                .set(key, value.to_string());
+        out.push(Md5Sum {
+            md5sum: md5sum.to_ascii_lowercase(),
+            path: path.to_path_buf(),
+        });
+    }
+    Ok(out)
+}
+
+/// The metadata from the `control` file inside a .deb
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct Control {
+    pub package: String,
+    pub version: String,
+    pub architecture: String,
+    pub installed_size: Option<u64>,
+}
+
+impl Control {
+    /// Make sure this is a spotify package that can run on the given architecture
+    pub fn validate(&self, arch: &Architecture) -> Result<()> {
+        if self.package != "spotify-client" {
+            bail!(
+                "Package is not spotify-client (package: {:?})",
+                self.package
+            );
         }
+        if self.architecture != "all" && self.architecture != arch.to_debian_str() {
+            bail!(
+                "Package is built for a different architecture (package: {:?}, expected: {:?})",
+                self.architecture,
+                arch.to_debian_str()
+            );
+        }
+        self.version
+            .parse::<Version>()
+            .context("Package has an invalid version")?;
+        Ok(())
     }
+}
 
-    Ok(out)
+pub fn parse_control_file(data: &str) -> Result<Control> {
+    let paragraphs = deb822::parse(data).context("Failed to parse control file")?;
+    let [paragraph] = &paragraphs[..] else {
+        bail!(
+            "Control file must contain exactly one paragraph, found {}",
+            paragraphs.len()
+        );
+    };
+
+    let required = |key: &str| {
+        paragraph
+            .get(key)
+            .map(String::from)
+            .with_context(|| anyhow!("Missing field in control file: `{}`", key))
+    };
+    let installed_size = paragraph
+        .get("Installed-Size")
+        .map(|value| {
+            value
+                .parse::<u64>()
+                .with_context(|| anyhow!("Invalid number in field `Installed-Size`: {:?}", value))
+        })
+        .transpose()?;
+
+    Ok(Control {
+        package: required("Package")?,
+        version: required("Version")?,
+        architecture: required("Architecture")?,
+        installed_size,
+    })
 }
 
 #[cfg(test)]
@@ -189,8 +623,11 @@ SHA256:
 "#;
         let parsed = parse_release_file(data)?;
         assert_eq!(parsed, {
-            let mut release = Release::default();
-            release.architectures = vec!["amd64".to_string(), "i386".to_string()];
+            let mut release = Release {
+                architectures: vec!["amd64".to_string(), "i386".to_string()],
+                date: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1651062615)),
+                ..Default::default()
+            };
             let m = &mut release.sha256_sums;
             m.insert(
                 "non-free/binary-amd64/Packages".into(),
@@ -233,6 +670,263 @@ SHA256:
         Ok(())
     }
 
+    #[test]
+    fn test_parse_control_file() -> Result<()> {
+        let data = "Package: spotify-client
+Version: 1:1.2.13.661.ga588f749
+Architecture: amd64
+Installed-Size: 312436
+Maintainer: Spotify <tux@spotify.com>
+Description: Spotify streaming music client
+";
+        let control = parse_control_file(data)?;
+        assert_eq!(
+            control,
+            Control {
+                package: "spotify-client".to_string(),
+                version: "1:1.2.13.661.ga588f749".to_string(),
+                architecture: "amd64".to_string(),
+                installed_size: Some(312436),
+            }
+        );
+        control.validate(&Architecture::Amd64)?;
+        assert!(control.validate(&Architecture::Arm64).is_err());
+
+        let other = Control {
+            package: "spotify-client-gnome-support".to_string(),
+            ..control.clone()
+        };
+        assert!(other.validate(&Architecture::Amd64).is_err());
+
+        assert!(parse_control_file("Package: spotify-client\n").is_err());
+        assert!(parse_control_file(&format!("{data}\n{data}")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_md5sums() -> Result<()> {
+        let data = "54c42a6025a0592b605b952641c6749c  usr/bin/spotify
+D41D8CD98F00B204E9800998ECF8427E  usr/share/spotify/Apps/xpui.spa
+";
+        assert_eq!(
+            parse_md5sums(data)?,
+            vec![
+                Md5Sum {
+                    md5sum: "54c42a6025a0592b605b952641c6749c".to_string(),
+                    path: PathBuf::from("usr/bin/spotify"),
+                },
+                Md5Sum {
+                    md5sum: "d41d8cd98f00b204e9800998ecf8427e".to_string(),
+                    path: PathBuf::from("usr/share/spotify/Apps/xpui.spa"),
+                },
+            ]
+        );
+
+        assert!(parse_md5sums("54c42a6025a0592b605b952641c6749c\n").is_err());
+        assert!(parse_md5sums("54c42a60  usr/bin/spotify\n").is_err());
+        assert!(parse_md5sums("54c42a6025a0592b605b952641c6749c  /etc/passwd\n").is_err());
+        assert!(parse_md5sums("54c42a6025a0592b605b952641c6749c  usr/../../x\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_architecture() -> Result<()> {
+        for name in [
+            "amd64", "i386", "arm64", "armhf", "armel", "riscv64", "ppc64el", "s390x",
+        ] {
+            let arch = name.parse::<Architecture>()?;
+            assert!(!matches!(arch, Architecture::Unknown(_)));
+            assert_eq!(arch.to_debian_str(), name);
+        }
+        assert_eq!(
+            "loong64".parse::<Architecture>()?,
+            Architecture::Unknown("loong64".to_string())
+        );
+        assert!("".parse::<Architecture>().is_err());
+        assert!("../amd64".parse::<Architecture>().is_err());
+        assert!("AMD64".parse::<Architecture>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_current_architecture() {
+        let arch = Architecture::current();
+        if cfg!(target_arch = "aarch64") {
+            assert_eq!(arch, Architecture::Arm64);
+        } else if cfg!(target_arch = "x86_64") {
+            assert_eq!(arch, Architecture::Amd64);
+        }
+        assert_ne!(arch.to_debian_str(), "aarch64");
+    }
+
+    #[test]
+    fn test_parse_release_data_file() -> Result<()> {
+        let data = std::fs::read_to_string("data/Release")?;
+        let parsed = parse_release_file(&data)?;
+        assert_eq!(parsed.architectures, &["amd64", "i386"]);
+        assert!(parsed
+            .sha256_sums
+            .contains_key("non-free/binary-amd64/Packages.gz"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_package_index_multiline() -> Result<()> {
+        let data = r#"Package: spotify-client
+Version: 1:1.1.84.716.gc5f8b819
+Filename: pool/non-free/s/spotify-client/spotify-client_1.1.84.716.gc5f8b819_amd64.deb
+sha256: 08e6b2666dc2a39624890e553a3046d05ecebe17bcc2fe930d49314b2fb812c7
+Description: Spotify streaming music client
+ Spotify is a digital music service that gives you access to
+ millions of songs.
+
+Package: spotify-client
+Version: 1:1.1.83.619.g45e29fb3
+Filename: pool/non-free/s/spotify-client/spotify-client_1.1.83.619.g45e29fb3_amd64.deb
+SHA256: 1b8b2d8e3a5fcba0a8a2b2b1ea35e5ee95e40b2b4e0cc40e6a59a1ae3f2ed1a3
+Size: 119700000
+"#;
+        let parsed = parse_package_index(data)?;
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(
+            parsed[0].description.as_deref(),
+            Some("Spotify streaming music client\nSpotify is a digital music service that gives you access to\nmillions of songs.")
+        );
+        assert_eq!(
+            parsed[0].sha256sum,
+            "08e6b2666dc2a39624890e553a3046d05ecebe17bcc2fe930d49314b2fb812c7"
+        );
+        assert_eq!(parsed[1].version, "1:1.1.83.619.g45e29fb3");
+        assert_eq!(parsed[1].size, Some(119700000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_package_index_invalid() {
+        assert!(parse_package_index("Package: spotify-client\nVersion: 1\n").is_err());
+        assert!(parse_package_index(
+            "Package: spotify-client\nVersion: 1\nFilename: a.deb\nSHA256: 00\nSize: abc\n"
+        )
+        .is_err());
+    }
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_version() -> Result<()> {
+        let v = "1:1.1.84.716.gc5f8b819".parse::<Version>()?;
+        assert_eq!(v.epoch, 1);
+        assert_eq!(v.upstream, "1.1.84.716.gc5f8b819");
+        assert_eq!(v.revision, "");
+        assert_eq!(v.to_string(), "1:1.1.84.716.gc5f8b819");
+
+        let v = "2.0-1-2".parse::<Version>()?;
+        assert_eq!(v.epoch, 0);
+        assert_eq!(v.upstream, "2.0-1");
+        assert_eq!(v.revision, "2");
+        assert_eq!(v.to_string(), "2.0-1-2");
+
+        assert!("".parse::<Version>().is_err());
+        assert!("a:1.0".parse::<Version>().is_err());
+        assert!("1:".parse::<Version>().is_err());
+        assert!("1.0 foo".parse::<Version>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        let cases = [
+            ("1.0", "1.0", Ordering::Equal),
+            ("1.0", "1.00", Ordering::Equal),
+            ("1.0", "1.1", Ordering::Less),
+            ("1.10", "1.9", Ordering::Greater),
+            ("1.0~rc1", "1.0", Ordering::Less),
+            ("1.0~~", "1.0~", Ordering::Less),
+            ("1.0~", "1.0", Ordering::Less),
+            ("1.0", "1.0a", Ordering::Less),
+            ("1.0a", "1.0+", Ordering::Less),
+            ("1.0.", "1.0a", Ordering::Greater),
+            ("1:0.1", "2.0", Ordering::Greater),
+            ("0:2.0", "2.0", Ordering::Equal),
+            ("1.0-1", "1.0-2", Ordering::Less),
+            ("1.0-10", "1.0-9", Ordering::Greater),
+            ("1.0-1", "1.0", Ordering::Greater),
+            (
+                "1:1.1.84.716.gc5f8b819",
+                "1:1.1.83.619.g45e29fb3",
+                Ordering::Greater,
+            ),
+            (
+                "1:1.2.8.923.g4f94bf0d",
+                "1:1.2.13.661.ga588f749",
+                Ordering::Less,
+            ),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(version(a).cmp(&version(b)), expected, "{a} vs {b}");
+            assert_eq!(
+                version(b).cmp(&version(a)),
+                expected.reverse(),
+                "{b} vs {a}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_date() -> Result<()> {
+        let date = parse_date("Mon, 09 Feb 2026 11:00:04 UTC")?;
+        assert_eq!(
+            date,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1770634804)
+        );
+        assert_eq!(format_date(date), "09 Feb 2026 11:00:04 UTC");
+
+        let date = parse_date("Mon, 09 Feb 2026 12:00:04 +0100")?;
+        assert_eq!(
+            date,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1770634804)
+        );
+
+        assert!(parse_date("Mon, 09 Foo 2026 11:00:04 UTC").is_err());
+        assert!(parse_date("Mon, 09 Feb 2026 11:00 UTC").is_err());
+        assert!(parse_date("Mon, 09 Feb 2026 11:00:04").is_err());
+        assert!(parse_date("Mon, 09 Feb 2026 -1:00:04 UTC").is_err());
+        assert!(parse_date("Mon, 09 Feb 2026 +1:00:04 UTC").is_err());
+        assert!(parse_date("Mon, 09 Feb 2026 11:-0:04 UTC").is_err());
+        assert!(parse_date("Mon, 09 Feb 2026 24:00:04 UTC").is_err());
+        assert!(parse_date("Mon, 09 Feb 2026 11:60:04 UTC").is_err());
+        assert!(parse_date("Mon, 09 Feb 2026 11:00:61 UTC").is_err());
+        assert!(parse_date("Mon, 09 Feb 2026 11:00:04 +-100").is_err());
+        parse_date("Wed, 31 Dec 2025 23:59:60 UTC")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_freshness() -> Result<()> {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1770634804);
+        let hour = Duration::from_secs(3600);
+
+        let release = Release {
+            date: Some(now - hour),
+            valid_until: Some(now + hour),
+            ..Default::default()
+        };
+        release.verify_freshness(now, None)?;
+        release.verify_freshness(now, Some(now - hour))?;
+        release.verify_freshness(now, Some(now - hour * 2))?;
+        // replayed release file
+        assert!(release.verify_freshness(now, Some(now)).is_err());
+        // expired release file
+        assert!(release.verify_freshness(now + hour * 2, None).is_err());
+
+        let release = Release::default();
+        release.verify_freshness(now, None)?;
+        assert!(release.verify_freshness(now, Some(now - hour)).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_parse_package_index() -> Result<()> {
         let data = r#"Package: spotify-client
@@ -262,11 +956,19 @@ Vendor: Spotify AB
             &[Pkg {
                 package: "spotify-client".into(),
                 version: "1:1.1.84.716.gc5f8b819".into(),
+                architecture: Some("amd64".into()),
                 filename:
                     "pool/non-free/s/spotify-client/spotify-client_1.1.84.716.gc5f8b819_amd64.deb"
                         .into(),
+                size: Some(119770140),
+                installed_size: Some(291694),
                 sha256sum: "08e6b2666dc2a39624890e553a3046d05ecebe17bcc2fe930d49314b2fb812c7"
                     .into(),
+                sha512sum: Some("3cc25f28ae791ac26607117a5df668f803ed8e58f0ace085010a6242fdde97766bdc1c752560850795c9b4324f3e019937fe9af2788a1946ebb70ee781f50d99".into()),
+                depends: Some("libasound2, libatk-bridge2.0-0, libatomic1, libcurl3-gnutls, libgbm1, libgconf-2-4, libglib2.0-0, libgtk-3-0, libnss3, libssl3 | libssl1.1 | libssl1.0.2 | libssl1.0.1 | libssl1.0.0, libxshmfence1, libxss1, libxtst6, xdg-utils".into()),
+                recommends: Some("libavcodec58 | libavcodec-extra58 | libavcodec57 | libavcodec-extra57 | libavcodec-ffmpeg56 | libavcodec-ffmpeg-extra56 | libavcodec54 | libavcodec-extra-54, libavformat58 | libavformat57 | libavformat-ffmpeg56 | libavformat54".into()),
+                homepage: Some("https://www.spotify.com".into()),
+                description: Some("Spotify streaming music client".into()),
             },]
         );
         Ok(())
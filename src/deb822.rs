@@ -0,0 +1,207 @@
+use crate::errors::*;
+
+/// A single paragraph of a deb822 file, like one package in a package index
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct Paragraph {
+    fields: Vec<(String, String)>,
+}
+
+impl Paragraph {
+    /// Lookup a field, field names are case-insensitive
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    fn insert(&mut self, key: &str, value: String) -> Result<()> {
+        if self.get(key).is_some() {
+            bail!("Duplicate field in paragraph: {:?}", key);
+        }
+        self.fields.push((key.to_string(), value));
+        Ok(())
+    }
+
+    fn append_line(&mut self, line: &str) -> Result<()> {
+        let (_, value) = self
+            .fields
+            .last_mut()
+            .context("Continuation line without a field")?;
+        value.push('\n');
+        value.push_str(line);
+        Ok(())
+    }
+}
+
+fn is_valid_field_name(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with(['#', '-'])
+        && key.bytes().all(|b| b.is_ascii_graphic() && b != b':')
+}
+
+/// Parse a deb822 file into its paragraphs
+///
+/// Continuation lines of multiline fields are joined with `\n`, with their leading space removed.
+/// A continuation line that only contains `.` stands for an empty line.
+pub fn parse(data: &str) -> Result<Vec<Paragraph>> {
+    let mut out = Vec::new();
+    let mut paragraph = Paragraph::default();
+
+    for line in data.lines() {
+        trace!("deb822 parser got line: {:?}", line);
+
+        if line.trim().is_empty() {
+            if !paragraph.is_empty() {
+                out.push(std::mem::take(&mut paragraph));
+            }
+        } else if line.starts_with('#') {
+            continue;
+        } else if let Some(line) = line.strip_prefix([' ', '\t']) {
+            let line = if line.trim_end() == "." { "" } else { line };
+            paragraph
+                .append_line(line)
+                .with_context(|| anyhow!("Unexpected continuation line: {:?}", line))?;
+        } else {
+            let (key, value) = line
+                .split_once(':')
+                .with_context(|| anyhow!("Line does not have key-value format: {:?}", line))?;
+            if !is_valid_field_name(key) {
+                bail!("Invalid field name: {:?}", key);
+            }
+            paragraph.insert(key, value.trim().to_string())?;
+        }
+    }
+
+    if !paragraph.is_empty() {
+        out.push(paragraph);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse_paragraphs() -> Result<()> {
+        let data = "# comment
+Package: spotify-client
+Version: 1:1.1.84.716.gc5f8b819
+Description: Spotify streaming music client
+ Listen to music
+ .
+ More text
+
+package: foo
+SHA256:
+ abcd 123 foo/bar
+";
+        let paragraphs = parse(data)?;
+        assert_eq!(paragraphs.len(), 2);
+        assert_eq!(paragraphs[0].get("Package"), Some("spotify-client"));
+        assert_eq!(
+            paragraphs[0].get("description"),
+            Some("Spotify streaming music client\nListen to music\n\nMore text")
+        );
+        assert_eq!(paragraphs[1].get("Package"), Some("foo"));
+        assert_eq!(paragraphs[1].get("SHA256"), Some("\nabcd 123 foo/bar"));
+        assert_eq!(paragraphs[1].get("Version"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_empty_continuation_lines() -> Result<()> {
+        let data = "Package: spotify-client
+Description: Spotify streaming music client
+ First paragraph
+ .
+ Second paragraph
+ .\t
+ .
+  . indented dot stays
+";
+        let paragraphs = parse(data)?;
+        assert_eq!(
+            paragraphs[0].get("Description"),
+            Some("Spotify streaming music client\nFirst paragraph\n\nSecond paragraph\n\n\n . indented dot stays")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(" continuation without field\n").is_err());
+        assert!(parse("no colon here\n").is_err());
+        assert!(parse("Package: a\npackage: b\n").is_err());
+        assert!(parse("-Package: a\n").is_err());
+        assert!(parse("Pack age: a\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_empty() -> Result<()> {
+        assert_eq!(parse("")?, vec![]);
+        assert_eq!(parse("\n\n  \n# only a comment\n")?, vec![]);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn parse_does_not_panic(data in "\\PC*") {
+            let _ = parse(&data);
+        }
+
+        #[test]
+        fn parse_does_not_panic_on_line_soup(
+            lines in prop::collection::vec("([ #\\t]?[A-Za-z:.\\- ]{0,12})", 0..16)
+        ) {
+            let _ = parse(&lines.join("\n"));
+        }
+
+        #[test]
+        fn parse_roundtrip(
+            fields in prop::collection::btree_map(
+                "[A-Za-z][A-Za-z0-9-]{0,15}",
+                prop::collection::vec("[!-~][ -~]{0,20}[!-~]", 1..4),
+                1..8,
+            )
+        ) {
+            // field names are case-insensitive, drop the ones that only differ in case
+            let mut seen = Vec::<String>::new();
+            let fields = fields
+                .into_iter()
+                .filter(|(k, _)| {
+                    let k = k.to_ascii_lowercase();
+                    let new = !seen.contains(&k);
+                    seen.push(k);
+                    new
+                })
+                .collect::<Vec<_>>();
+
+            let mut data = String::new();
+            for (key, lines) in &fields {
+                data.push_str(&format!("{key}: {}\n", lines[0]));
+                for line in &lines[1..] {
+                    data.push_str(&format!(" {line}\n"));
+                }
+            }
+
+            let paragraphs = parse(&data).unwrap();
+            prop_assert_eq!(paragraphs.len(), 1);
+            for (key, lines) in &fields {
+                let value = lines.join("\n");
+                prop_assert_eq!(paragraphs[0].get(key), Some(value.as_str()));
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use crate::apt::Client;
+use crate::config::ConfigFile;
+use crate::deb;
+use crate::paths;
+use crate::pgp;
+use std::path::Path;
+
+/// Less free space than this in the data directory is going to break updates
+pub const MIN_FREE_SPACE: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Pass(String),
+    Warn(String),
+    Fail(String),
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<(&'static str, Outcome)>,
+}
+
+impl Report {
+    pub fn push(&mut self, name: &'static str, outcome: Outcome) {
+        self.checks.push((name, outcome));
+    }
+
+    pub fn failures(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Fail(_)))
+            .count()
+    }
+
+    pub fn print(&self) {
+        for (name, outcome) in &self.checks {
+            let (label, detail) = match outcome {
+                Outcome::Pass(detail) => ("[ ok ]", detail),
+                Outcome::Warn(detail) => ("[warn]", detail),
+                Outcome::Fail(detail) => ("[FAIL]", detail),
+            };
+            println!("{label} {name}: {detail}");
+        }
+    }
+}
+
+/// Check an external program is installed, `required` decides if it's a failure or a warning
+pub fn program(bin: &str, required: bool, missing: &str) -> Outcome {
+    match pgp::find_in_path(bin) {
+        Some(path) => Outcome::Pass(format!("found at {:?}", path)),
+        None if required => Outcome::Fail(format!("`{bin}` not found in PATH, {missing}")),
+        None => Outcome::Warn(format!("`{bin}` not found in PATH, {missing}")),
+    }
+}
+
+/// Check the external programs needed by the configured pgp backend
+pub fn pgp_backend(backend: pgp::Backend) -> Outcome {
+    match backend {
+        pgp::Backend::Auto => program("sqv", false, "using the native pgp implementation"),
+        pgp::Backend::Sqv => program("sqv", true, "signatures can't be verified"),
+        pgp::Backend::Gpgv => program("gpgv", true, "signatures can't be verified"),
+        pgp::Backend::Native => Outcome::Pass("using the native pgp implementation".to_string()),
+    }
+}
+
+/// Check the keyring can be read, keys rpgp can't parse are only fatal for the native backend
+pub fn keyring(keyring: &Path, backend: pgp::Backend) -> Outcome {
+    let files = match pgp::keyring_files(keyring) {
+        Ok(files) => files,
+        Err(err) => return Outcome::Fail(format!("{err:#}")),
+    };
+    if let Some(path) = files.iter().find(|path| std::fs::File::open(path).is_err()) {
+        return Outcome::Fail(format!("Failed to read keyring at {:?}", path));
+    }
+
+    let backend = backend.resolve();
+    let outcome = match pgp::load_keyring(keyring) {
+        Ok(keys) => return Outcome::Pass(format!("{} keys loaded from {:?}", keys.len(), keyring)),
+        Err(err) => format!("{err:#}"),
+    };
+    if backend == pgp::Backend::Native {
+        Outcome::Fail(outcome)
+    } else {
+        Outcome::Warn(format!(
+            "{outcome}, {:?} may still be able to use it",
+            backend
+        ))
+    }
+}
+
+/// Check the data directory is writable, it's created on the first start so it may not exist yet
+pub fn data_dir(path: &Path) -> Outcome {
+    match std::fs::metadata(path) {
+        Ok(metadata) if !metadata.is_dir() => {
+            return Outcome::Fail(format!("{:?} is not a directory", path))
+        }
+        Ok(_) => (),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Outcome::Warn(format!("{:?} doesn't exist yet", path))
+        }
+        Err(err) => return Outcome::Fail(format!("Failed to access {:?}: {}", path, err)),
+    }
+    match tempfile::NamedTempFile::new_in(path) {
+        Ok(_) => Outcome::Pass(format!("{:?} is writable", path)),
+        Err(err) => Outcome::Fail(format!("Failed to create a file in {:?}: {}", path, err)),
+    }
+}
+
+/// Check the free space of the filesystem `path` is going to be on, it doesn't need to exist yet
+pub fn free_space(path: &Path) -> Outcome {
+    let path = path.ancestors().find(|path| path.exists()).unwrap_or(path);
+    match nix::sys::statvfs::statvfs(path) {
+        Ok(stat) => {
+            // the types of these fields differ between platforms
+            #[allow(clippy::unnecessary_cast)]
+            let available = stat.blocks_available() as u64 * stat.fragment_size() as u64;
+            free_space_outcome(available)
+        }
+        Err(err) => Outcome::Fail(format!("Failed to query free space of {:?}: {}", path, err)),
+    }
+}
+
+fn free_space_outcome(available: u64) -> Outcome {
+    let msg = format!("{:.1} MiB available", available as f64 / 1024.0 / 1024.0);
+    if available < MIN_FREE_SPACE {
+        Outcome::Fail(format!(
+            "{msg}, at least {} MiB are needed for updates",
+            MIN_FREE_SPACE / 1024 / 1024
+        ))
+    } else {
+        Outcome::Pass(msg)
+    }
+}
+
+/// Check the config file parses, returns the config to use for the remaining checks
+pub fn config() -> (Outcome, ConfigFile) {
+    let path = match ConfigFile::locate_file() {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            let outcome = Outcome::Pass("no config file found, using default config".to_string());
+            return (outcome, ConfigFile::default());
+        }
+        Err(err) => return (Outcome::Fail(format!("{err:#}")), ConfigFile::default()),
+    };
+    match ConfigFile::load_from(&path) {
+        Ok(cf) => (Outcome::Pass(format!("loaded {:?}", path)), cf),
+        Err(err) => (Outcome::Fail(format!("{err:#}")), ConfigFile::default()),
+    }
+}
+
+pub async fn state_file() -> Outcome {
+    match paths::read_state_file().await {
+        Ok(None) => Outcome::Pass("no state file yet".to_string()),
+        Ok(Some(state)) => match state.version.parse::<deb::Version>() {
+            Ok(_) => Outcome::Pass(format!("installed version is {}", state.version)),
+            Err(err) => Outcome::Fail(format!("{err:#}")),
+        },
+        Err(err) => Outcome::Fail(format!("{err:#}")),
+    }
+}
+
+/// Fetch and verify the package index of the configured repository
+pub async fn repository(client: &Client, pinned_version: Option<&deb::Version>) -> Outcome {
+    let index = match client.fetch_pkg_index(None).await {
+        Ok(index) => index,
+        Err(err) => return Outcome::Fail(format!("{err:#}")),
+    };
+    match index.select_spotify_client(pinned_version) {
+        Ok(pkg) => Outcome::Pass(format!(
+            "{} ({}) offers spotify {}",
+            client.repository_url(),
+            client.suite(),
+            pkg.version
+        )),
+        Err(err) => Outcome::Fail(format!("{err:#}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::*;
+
+    #[test]
+    fn test_free_space() {
+        assert!(matches!(
+            free_space_outcome(MIN_FREE_SPACE),
+            Outcome::Pass(_)
+        ));
+        assert!(matches!(
+            free_space_outcome(MIN_FREE_SPACE - 1),
+            Outcome::Fail(_)
+        ));
+    }
+
+    #[test]
+    fn test_data_dir() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let missing = dir.path().join("spotify-launcher");
+        assert!(matches!(data_dir(&missing), Outcome::Warn(_)));
+        assert!(!missing.exists());
+        // the free space of the filesystem it's going to be created on
+        assert!(
+            !matches!(free_space(&missing), Outcome::Fail(msg) if msg.starts_with("Failed to query"))
+        );
+        assert!(matches!(data_dir(dir.path()), Outcome::Pass(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyring() {
+        let path = Path::new("data/pubkey_5384CE82BA52C83A.gpg");
+        assert!(matches!(
+            keyring(path, pgp::Backend::Native),
+            Outcome::Pass(_)
+        ));
+
+        // rpgp can't parse this, but an external tool might
+        let path = Path::new("data/Release");
+        assert!(matches!(
+            keyring(path, pgp::Backend::Native),
+            Outcome::Fail(_)
+        ));
+        assert!(matches!(
+            keyring(path, pgp::Backend::Gpgv),
+            Outcome::Warn(_)
+        ));
+        assert!(matches!(
+            keyring(Path::new("data/nonexistent.gpg"), pgp::Backend::Gpgv),
+            Outcome::Fail(_)
+        ));
+    }
+
+    #[test]
+    fn test_report_failures() {
+        let mut report = Report::default();
+        report.push("a", Outcome::Pass("ok".to_string()));
+        report.push("b", Outcome::Warn("hmm".to_string()));
+        assert_eq!(report.failures(), 0);
+        report.push("c", Outcome::Fail("nope".to_string()));
+        assert_eq!(report.failures(), 1);
+    }
+}
@@ -1,10 +1,11 @@
-use crate::args::Args;
+use crate::deb;
 use crate::errors::*;
-use crate::paths;
+use bzip2::read::BzDecoder;
 use libflate::gzip::Decoder;
 use lzma::LzmaReader;
+use std::collections::HashSet;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use tokio::fs;
 
 async fn atomic_swap(src: &Path, target: &Path) -> Result<()> {
@@ -17,35 +18,250 @@ async fn atomic_swap(src: &Path, target: &Path) -> Result<()> {
     Ok(())
 }
 
-async fn extract_data<R: Read>(
-    mut tar: tar::Archive<R>,
-    args: &Args,
-    install_path: &Path,
-) -> Result<()> {
-    let new_install_path = if let Some(path) = args.install_dir.clone() {
-        path
-    } else {
-        paths::new_install_path()?
+/// Open a compressed tar member of a .deb (e.g. `data.tar.xz`), returns `None` for other members
+fn tar_reader<'a, R: Read + 'a>(
+    name: &[u8],
+    member: &str,
+    entry: R,
+) -> Result<Option<Box<dyn Read + 'a>>> {
+    let Some(ext) = name.strip_prefix(member.as_bytes()) else {
+        return Ok(None);
     };
+    let reader: Box<dyn Read + 'a> = match ext {
+        b".gz" => Box::new(Decoder::new(entry)?),
+        b".xz" => Box::new(LzmaReader::new_decompressor(entry)?),
+        b".zst" => Box::new(zstd::Decoder::new(entry)?),
+        b".bz2" => Box::new(BzDecoder::new(entry)),
+        b"" => Box::new(entry),
+        _ => return Ok(None),
+    };
+    debug!("Found {} in .deb", String::from_utf8_lossy(name));
+    Ok(Some(reader))
+}
+
+/// Entries that were modified or left out while extracting
+#[derive(Debug, PartialEq, Default)]
+pub struct Summary {
+    pub extracted: usize,
+    pub skipped: Vec<(PathBuf, &'static str)>,
+    /// Files that had their setuid/setgid bits removed
+    pub stripped: Vec<PathBuf>,
+}
+
+impl Summary {
+    fn log(&self) {
+        info!("Extracted {} entries", self.extracted);
+        if !self.skipped.is_empty() {
+            warn!("Skipped {} entries:", self.skipped.len());
+            for (path, reason) in &self.skipped {
+                warn!("  {:?} ({})", path, reason);
+            }
+        }
+        if !self.stripped.is_empty() {
+            warn!(
+                "Removed setuid/setgid bits from {} files: {:?}",
+                self.stripped.len(),
+                self.stripped
+            );
+        }
+    }
+}
+
+/// Resolve `.` and `..` without touching the filesystem, returns `None` if the path escapes its root
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::Normal(name) => out.push(name),
+            Component::ParentDir => {
+                if !out.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(out)
+}
+
+/// Resolve the target of a symlink at `path` relative to the root of the archive
+///
+/// Returns `None` if it escapes the root or goes through one of the `symlinks` extracted before,
+/// these can't be resolved without touching the filesystem.
+fn resolve_symlink(path: &Path, target: &Path, symlinks: &HashSet<PathBuf>) -> Option<PathBuf> {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let mut out = PathBuf::new();
+    for component in parent.join(target).components() {
+        match component {
+            Component::CurDir => (),
+            _ if symlinks.contains(&out) => return None,
+            Component::Normal(name) => out.push(name),
+            Component::ParentDir => {
+                if !out.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(out)
+}
+
+/// Extract a tar archive, refusing anything that could write outside of `dest`
+fn unpack_tar<R: Read>(mut tar: tar::Archive<R>, dest: &Path) -> Result<Summary> {
+    std::fs::create_dir_all(dest)
+        .with_context(|| anyhow!("Failed to create directory {:?}", dest))?;
+    let dest = dest.canonicalize()?;
+
+    let mut summary = Summary::default();
+    let mut symlinks = HashSet::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if path.has_root()
+            || path
+                .components()
+                .any(|c| !matches!(c, Component::CurDir | Component::Normal(_)))
+        {
+            bail!("Refusing to extract entry with unsafe path: {:?}", path);
+        }
+
+        let entry_type = entry.header().entry_type();
+        match entry_type {
+            tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::Directory => (),
+            tar::EntryType::Symlink => {
+                let target = entry
+                    .link_name()?
+                    .with_context(|| anyhow!("Symlink without target: {:?}", path))?;
+                if resolve_symlink(&path, &target, &symlinks).is_none() {
+                    bail!(
+                        "Refusing to extract symlink that escapes the install directory: {:?} -> {:?}",
+                        path,
+                        target
+                    );
+                }
+                symlinks.extend(normalize(&path));
+            }
+            tar::EntryType::Link => {
+                let target = entry.link_name()?;
+                if target.is_none_or(|target| target.has_root() || normalize(&target).is_none()) {
+                    summary.skipped.push((path, "hardlink outside of archive"));
+                    continue;
+                }
+            }
+            tar::EntryType::Char | tar::EntryType::Block => {
+                summary.skipped.push((path, "device node"));
+                continue;
+            }
+            tar::EntryType::Fifo => {
+                summary.skipped.push((path, "fifo"));
+                continue;
+            }
+            _ => {
+                summary.skipped.push((path, "unsupported entry type"));
+                continue;
+            }
+        }
+
+        if entry.header().mode()? & 0o6000 != 0 {
+            summary.stripped.push(path.clone());
+        }
+        // only the regular permission bits are applied, this drops setuid/setgid/sticky
+        entry.set_preserve_permissions(false);
+
+        trace!("Extracting {:?} ({:?})", path, entry_type);
+        if !entry
+            .unpack_in(&dest)
+            .with_context(|| anyhow!("Failed to extract {:?}", path))?
+        {
+            bail!("Refusing to extract entry with unsafe path: {:?}", path);
+        }
+        summary.extracted += 1;
+    }
+    Ok(summary)
+}
 
+/// Extract the data member of a .deb into a directory
+pub fn unpack<R: Read>(deb: R, dest: &Path) -> Result<Summary> {
+    let mut ar = ar::Archive::new(deb);
+    while let Some(entry) = ar.next_entry() {
+        let mut entry = entry?;
+        let name = entry.header().identifier().to_vec();
+        let data = tar_reader(&name, "data.tar", &mut entry)?;
+        if let Some(data) = data {
+            let summary =
+                unpack_tar(tar::Archive::new(data), dest).context("Failed to extract spotify")?;
+            summary.log();
+            return Ok(summary);
+        }
+    }
+    bail!("Failed to find data entry in .deb");
+}
+
+/// Read a file from the control member of a .deb, returns `None` if the file doesn't exist
+fn control_file<R: Read>(deb: R, filename: &str) -> Result<Option<String>> {
+    let mut ar = ar::Archive::new(deb);
+    while let Some(entry) = ar.next_entry() {
+        let mut entry = entry?;
+        let name = entry.header().identifier().to_vec();
+        let control = tar_reader(&name, "control.tar", &mut entry)?;
+        let Some(control) = control else {
+            continue;
+        };
+
+        let mut tar = tar::Archive::new(control);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?;
+            if path.strip_prefix(".").unwrap_or(&path) != Path::new(filename) {
+                continue;
+            }
+            let mut buf = String::new();
+            entry
+                .read_to_string(&mut buf)
+                .with_context(|| anyhow!("Failed to read {:?} from control entry", filename))?;
+            return Ok(Some(buf));
+        }
+        return Ok(None);
+    }
+    bail!("Failed to find control entry in .deb");
+}
+
+/// Read the package metadata from the control member of a .deb
+pub fn control<R: Read>(deb: R) -> Result<deb::Control> {
+    let control = control_file(deb, "control")?.context("Failed to find control file in .deb")?;
+    deb::parse_control_file(&control)
+}
+
+/// Read the md5sums of the package's files, if the .deb has them
+pub fn md5sums<R: Read>(deb: R) -> Result<Option<String>> {
+    let Some(md5sums) = control_file(deb, "md5sums")? else {
+        return Ok(None);
+    };
+    deb::parse_md5sums(&md5sums)?;
+    Ok(Some(md5sums))
+}
+
+/// Extract a .deb into `new_install_path` and swap it with `install_path` afterwards
+pub async fn pkg<R: Read>(deb: R, install_path: &Path, new_install_path: &Path) -> Result<()> {
     info!("Extracting to {:?}...", new_install_path);
-    tar.unpack(&new_install_path)
-        .context("Failed to extract spotify")?;
+    unpack(deb, new_install_path)?;
 
     if install_path != new_install_path {
-        if let Err(err) = atomic_swap(&new_install_path, install_path).await {
+        if let Err(err) = atomic_swap(new_install_path, install_path).await {
             warn!("Failed to swap {new_install_path:?} with {install_path:?}: {err:#}");
             debug!("Falling back to non-atomic swap, removing old directory...");
             fs::remove_dir_all(&install_path)
                 .await
                 .context("Failed to delete old directory")?;
             debug!("Moving new directory in place...");
-            fs::rename(&new_install_path, &install_path)
+            fs::rename(new_install_path, install_path)
                 .await
                 .context("Failed to move new directory in place")?;
         } else {
             debug!("Removing old directory...");
-            if let Err(err) = fs::remove_dir_all(&new_install_path).await {
+            if let Err(err) = fs::remove_dir_all(new_install_path).await {
                 warn!("Failed to delete old directory: {:#}", err);
             }
         }
@@ -53,25 +269,303 @@ async fn extract_data<R: Read>(
     Ok(())
 }
 
-pub async fn pkg<R: Read>(deb: R, args: &Args, install_path: &Path) -> Result<()> {
-    let mut ar = ar::Archive::new(deb);
-    while let Some(entry) = ar.next_entry() {
-        let mut entry = entry?;
-        match entry.header().identifier() {
-            b"data.tar.gz" => {
-                debug!("Found data.tar.gz in .deb");
-                let decoder = Decoder::new(&mut entry)?;
-                let tar = tar::Archive::new(decoder);
-                return extract_data(tar, args, install_path).await;
-            }
-            b"data.tar.xz" => {
-                debug!("Found data.tar.xz in .deb");
-                let decoder = LzmaReader::new_decompressor(entry)?;
-                let tar = tar::Archive::new(decoder);
-                return extract_data(tar, args, install_path).await;
-            }
-            _ => (),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn data_tar() -> Result<Vec<u8>> {
+        let mut tar = tar::Builder::new(Vec::new());
+        let data = b"#!/bin/sh\necho ohai\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        tar.append_data(&mut header, "usr/bin/spotify", &data[..])?;
+        Ok(tar.into_inner()?)
+    }
+
+    fn control_tar() -> Result<Vec<u8>> {
+        let mut tar = tar::Builder::new(Vec::new());
+        let data =
+            b"Package: spotify-client\nVersion: 1:1.2.13.661.ga588f749\nArchitecture: amd64\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "./control", &data[..])?;
+
+        let data = b"54c42a6025a0592b605b952641c6749c  usr/bin/spotify\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "./md5sums", &data[..])?;
+        Ok(tar.into_inner()?)
+    }
+
+    fn build_deb(name: &str, data: &[u8]) -> Result<Vec<u8>> {
+        let mut ar = ar::Builder::new(Vec::new());
+        let control = lzma::compress(&control_tar()?, 6)?;
+        for (name, data) in [
+            ("debian-binary", &b"2.0\n"[..]),
+            ("control.tar.xz", &control),
+            (name, data),
+        ] {
+            let header = ar::Header::new(name.as_bytes().to_vec(), data.len() as u64);
+            ar.append(&header, data)?;
         }
+        Ok(ar.into_inner()?)
+    }
+
+    fn assert_unpacks(deb: &[u8]) -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        unpack(deb, dir.path())?;
+        let data = std::fs::read(dir.path().join("usr/bin/spotify"))?;
+        assert_eq!(data, b"#!/bin/sh\necho ohai\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_gz() -> Result<()> {
+        let mut encoder = libflate::gzip::Encoder::new(Vec::new())?;
+        encoder.write_all(&data_tar()?)?;
+        let data = encoder.finish().into_result()?;
+        assert_unpacks(&build_deb("data.tar.gz", &data)?)
+    }
+
+    #[test]
+    fn test_unpack_xz() -> Result<()> {
+        let data = lzma::compress(&data_tar()?, 6)?;
+        assert_unpacks(&build_deb("data.tar.xz", &data)?)
+    }
+
+    #[test]
+    fn test_unpack_zst() -> Result<()> {
+        let data = zstd::encode_all(&data_tar()?[..], 0)?;
+        assert_unpacks(&build_deb("data.tar.zst", &data)?)
+    }
+
+    #[test]
+    fn test_unpack_bz2() -> Result<()> {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(&data_tar()?)?;
+        let data = encoder.finish()?;
+        assert_unpacks(&build_deb("data.tar.bz2", &data)?)
+    }
+
+    #[test]
+    fn test_unpack_uncompressed() -> Result<()> {
+        assert_unpacks(&build_deb("data.tar", &data_tar()?)?)
+    }
+
+    #[test]
+    fn test_control() -> Result<()> {
+        let deb = build_deb("data.tar", &data_tar()?)?;
+        let control = control(&deb[..])?;
+        assert_eq!(control.package, "spotify-client");
+        assert_eq!(control.version, "1:1.2.13.661.ga588f749");
+        assert_eq!(control.architecture, "amd64");
+        Ok(())
+    }
+
+    #[test]
+    fn test_md5sums() -> Result<()> {
+        let deb = build_deb("data.tar", &data_tar()?)?;
+        let md5sums = md5sums(&deb[..])?.unwrap();
+        let md5sums = deb::parse_md5sums(&md5sums)?;
+        assert_eq!(md5sums.len(), 1);
+        assert_eq!(md5sums[0].path, Path::new("usr/bin/spotify"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_control_zst() -> Result<()> {
+        let mut ar = ar::Builder::new(Vec::new());
+        let data = zstd::encode_all(&control_tar()?[..], 0)?;
+        ar.append(
+            &ar::Header::new(b"control.tar.zst".to_vec(), data.len() as u64),
+            &data[..],
+        )?;
+        let deb = ar.into_inner()?;
+        assert_eq!(control(&deb[..])?.package, "spotify-client");
+        Ok(())
+    }
+
+    /// Build a tar entry by hand, the tar builder refuses to create most of these
+    fn raw_entry(
+        tar: &mut tar::Builder<Vec<u8>>,
+        path: &str,
+        entry_type: tar::EntryType,
+        link: Option<&str>,
+        mode: u32,
+    ) -> Result<()> {
+        let mut header = tar::Header::new_old();
+        let old = header.as_old_mut();
+        old.name[..path.len()].copy_from_slice(path.as_bytes());
+        if let Some(link) = link {
+            old.linkname[..link.len()].copy_from_slice(link.as_bytes());
+        }
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(0);
+        header.set_cksum();
+        tar.append(&header, &b""[..])?;
+        Ok(())
+    }
+
+    fn unpack_entries(
+        entries: &[(&str, tar::EntryType, Option<&str>, u32)],
+    ) -> Result<(tempfile::TempDir, Result<Summary>)> {
+        let mut tar = tar::Builder::new(Vec::new());
+        for (path, entry_type, link, mode) in entries {
+            raw_entry(&mut tar, path, *entry_type, *link, *mode)?;
+        }
+        let tar = tar.into_inner()?;
+        let dir = tempfile::tempdir()?;
+        let result = unpack_tar(tar::Archive::new(&tar[..]), &dir.path().join("install"));
+        Ok((dir, result))
+    }
+
+    #[test]
+    fn test_unpack_rejects_traversal() -> Result<()> {
+        let (dir, result) =
+            unpack_entries(&[("../escaped", tar::EntryType::Regular, None, 0o644)])?;
+        assert!(result.is_err());
+        assert!(!dir.path().join("escaped").exists());
+
+        let (_dir, result) =
+            unpack_entries(&[("usr/../../escaped", tar::EntryType::Regular, None, 0o644)])?;
+        assert!(result.is_err());
+
+        let (_dir, result) =
+            unpack_entries(&[("/tmp/escaped", tar::EntryType::Regular, None, 0o644)])?;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_symlinks() -> Result<()> {
+        let (dir, result) = unpack_entries(&[
+            (
+                "./usr/share/spotify/",
+                tar::EntryType::Directory,
+                None,
+                0o755,
+            ),
+            (
+                "./usr/share/spotify/spotify",
+                tar::EntryType::Regular,
+                None,
+                0o755,
+            ),
+            (
+                "./usr/bin/spotify",
+                tar::EntryType::Symlink,
+                Some("../share/spotify/spotify"),
+                0o777,
+            ),
+        ])?;
+        assert_eq!(result?.extracted, 3);
+        let link = std::fs::read_link(dir.path().join("install/usr/bin/spotify"))?;
+        assert_eq!(link, Path::new("../share/spotify/spotify"));
+
+        let (_dir, result) = unpack_entries(&[(
+            "usr/bin/spotify",
+            tar::EntryType::Symlink,
+            Some("../../../etc/passwd"),
+            0o777,
+        )])?;
+        assert!(result.is_err());
+
+        let (_dir, result) = unpack_entries(&[(
+            "usr/bin/spotify",
+            tar::EntryType::Symlink,
+            Some("/etc/passwd"),
+            0o777,
+        )])?;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_chained_symlinks() -> Result<()> {
+        // `a/b/..` looks harmless, but `a/b` points to the install directory itself
+        let (dir, result) = unpack_entries(&[
+            ("./a/b", tar::EntryType::Symlink, Some(".."), 0o777),
+            ("./c", tar::EntryType::Symlink, Some("a/b/.."), 0o777),
+        ])?;
+        assert!(result.is_err());
+        assert!(!dir.path().join("install/c").exists());
+
+        let (_dir, result) = unpack_entries(&[
+            ("a", tar::EntryType::Symlink, Some("."), 0o777),
+            ("a/b", tar::EntryType::Symlink, Some(".."), 0o777),
+        ])?;
+        assert!(result.is_err());
+
+        // pointing to another symlink is fine
+        let (_dir, result) = unpack_entries(&[
+            ("a/b", tar::EntryType::Symlink, Some(".."), 0o777),
+            ("c", tar::EntryType::Symlink, Some("a/b"), 0o777),
+        ])?;
+        assert_eq!(result?.extracted, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_skips_special_files() -> Result<()> {
+        let (dir, result) = unpack_entries(&[
+            ("dev/null", tar::EntryType::Char, None, 0o666),
+            ("dev/sda", tar::EntryType::Block, None, 0o660),
+            ("fifo", tar::EntryType::Fifo, None, 0o644),
+            ("passwd", tar::EntryType::Link, Some("../etc/passwd"), 0o644),
+            ("file", tar::EntryType::Regular, None, 0o644),
+        ])?;
+        let summary = result?;
+        assert_eq!(summary.extracted, 1);
+        assert_eq!(
+            summary.skipped,
+            vec![
+                (PathBuf::from("dev/null"), "device node"),
+                (PathBuf::from("dev/sda"), "device node"),
+                (PathBuf::from("fifo"), "fifo"),
+                (PathBuf::from("passwd"), "hardlink outside of archive"),
+            ]
+        );
+        assert!(!dir.path().join("install/dev").exists());
+        assert!(!dir.path().join("install/passwd").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_strips_setuid() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, result) = unpack_entries(&[
+            ("suid", tar::EntryType::Regular, None, 0o4755),
+            ("sgid", tar::EntryType::Regular, None, 0o2755),
+            ("normal", tar::EntryType::Regular, None, 0o755),
+        ])?;
+        let summary = result?;
+        assert_eq!(
+            summary.stripped,
+            vec![PathBuf::from("suid"), PathBuf::from("sgid")]
+        );
+        for name in ["suid", "sgid", "normal"] {
+            let mode = std::fs::metadata(dir.path().join("install").join(name))?
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o6000, 0, "{name}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_missing_data() -> Result<()> {
+        let deb = build_deb("foo.tar.gz", b"")?;
+        let dir = tempfile::tempdir()?;
+        assert!(unpack(&deb[..], dir.path()).is_err());
+        Ok(())
     }
-    bail!("Failed to find data entry in .deb");
 }
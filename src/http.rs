@@ -4,9 +4,24 @@ use reqwest::{
     header::{HeaderMap, HeaderValue, RANGE},
     Response, StatusCode,
 };
+use std::fmt;
 use std::time::Duration;
 use tokio::time;
 
+/// The server has no data after the requested offset, the partial download is already complete
+#[derive(Debug)]
+pub struct RangeNotSatisfiable {
+    pub offset: u64,
+}
+
+impl fmt::Display for RangeNotSatisfiable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Server has no data after offset {}", self.offset)
+    }
+}
+
+impl std::error::Error for RangeNotSatisfiable {}
+
 pub struct Client {
     client: reqwest::Client,
     timeout: Option<Duration>,
@@ -45,6 +60,9 @@ impl Client {
                 .context("Failed to send http request")?;
 
             let status = resp.status();
+            if let (Some(offset), StatusCode::RANGE_NOT_SATISFIABLE) = (offset, status) {
+                return Err(RangeNotSatisfiable { offset }.into());
+            }
             if !status.is_success() {
                 bail!("Unexpected http status code: {:?}", status);
             }
@@ -78,13 +96,24 @@ impl Client {
         Ok(body.to_vec())
     }
 
+    /// Download from `offset`, the download starts from the beginning instead if the server
+    /// doesn't support resumption, check `Download::progress` for where it actually starts
     pub async fn fetch_stream(&self, url: &str, offset: Option<u64>) -> Result<Download> {
         debug!("Downloading {:?}...", url);
         let resp = self.send_get(url, offset).await?;
 
-        if offset.is_some() && resp.status() != StatusCode::PARTIAL_CONTENT {
-            bail!("Download server does not support resumption");
-        }
+        let offset = match (offset, resp.status()) {
+            (Some(_), StatusCode::PARTIAL_CONTENT) => offset,
+            (Some(_), StatusCode::OK) => {
+                debug!("Server ignored range request, downloading full file");
+                None
+            }
+            (Some(_), status) => bail!(
+                "Unexpected http status code for range request: {:?}",
+                status
+            ),
+            (None, _) => None,
+        };
 
         let progress = offset.unwrap_or(0);
         let total = resp.content_length().unwrap_or(0) + progress;
@@ -125,3 +154,63 @@ impl Download {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    /// Answer a single http request with `response`, returns the request that was received
+    fn serve_once(
+        response: &'static [u8],
+    ) -> Result<(SocketAddr, thread::JoinHandle<Result<String>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = thread::spawn(move || -> Result<String> {
+            let (mut stream, _) = listener.accept()?;
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf)?;
+            stream.write_all(response)?;
+            Ok(String::from_utf8_lossy(&buf[..n]).to_lowercase())
+        });
+        Ok((addr, server))
+    }
+
+    #[tokio::test]
+    async fn test_range_not_satisfiable() -> Result<()> {
+        let (addr, server) =
+            serve_once(b"HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\n\r\n")?;
+
+        let client = Client::new(Some(5))?;
+        let err = client
+            .fetch_stream(&format!("http://{addr}/spotify.deb"), Some(5))
+            .await
+            .err()
+            .context("Expected download to fail")?;
+        let request = server.join().unwrap()?;
+        assert!(request.contains("range: bytes=5-"));
+        assert_eq!(err.downcast_ref::<RangeNotSatisfiable>().unwrap().offset, 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_range_not_supported() -> Result<()> {
+        let (addr, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nohai!")?;
+
+        let client = Client::new(Some(5))?;
+        let mut dl = client
+            .fetch_stream(&format!("http://{addr}/spotify.deb"), Some(2))
+            .await?;
+        assert_eq!((dl.progress, dl.total), (0, 5));
+        let mut body = Vec::new();
+        while let Some(chunk) = dl.chunk().await? {
+            body.extend_from_slice(&chunk);
+        }
+        assert_eq!(body, b"ohai!");
+        assert_eq!(dl.progress, 5);
+        server.join().unwrap()?;
+        Ok(())
+    }
+}
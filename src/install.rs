@@ -0,0 +1,309 @@
+use crate::deb;
+use crate::errors::*;
+use crate::extract;
+use crate::paths;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+pub const DEFAULT_MAX_VERSIONS: usize = 2;
+
+/// Installed spotify versions, each in its own directory with a `current` symlink pointing to the active one
+pub struct Installs {
+    path: PathBuf,
+}
+
+impl Installs {
+    pub fn new(path: PathBuf) -> Installs {
+        Installs { path }
+    }
+
+    pub fn open() -> Result<Installs> {
+        Ok(Installs::new(paths::spotify_launcher_path()?))
+    }
+
+    pub fn current_path(&self) -> PathBuf {
+        self.path.join("current")
+    }
+
+    fn versions_path(&self) -> PathBuf {
+        self.path.join("versions")
+    }
+
+    fn validate_version(version: &str) -> Result<()> {
+        // the version is used as directory name, a valid debian version can't contain `/`,
+        // and a leading `.` would collide with `..` and the temporary directories
+        version.parse::<deb::Version>()?;
+        if version.starts_with('.') {
+            bail!("Invalid version for install directory: {:?}", version);
+        }
+        Ok(())
+    }
+
+    pub fn version_path(&self, version: &str) -> Result<PathBuf> {
+        Self::validate_version(version)?;
+        Ok(self.versions_path().join(version))
+    }
+
+    /// The md5sums of an installed version, copied from its .deb
+    pub fn md5sums_path(&self, version: &str) -> Result<PathBuf> {
+        Self::validate_version(version)?;
+        Ok(self.versions_path().join(format!("{version}.md5sums")))
+    }
+
+    /// The version the `current` symlink points to, if any
+    pub async fn current_version(&self) -> Result<Option<String>> {
+        let path = self.current_path();
+        let target = match fs::read_link(&path).await {
+            Ok(target) => target,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| anyhow!("Failed to read symlink at {:?}", path))
+            }
+        };
+        let version = target
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| anyhow!("Unexpected symlink target at {:?}: {:?}", path, target))?;
+        Ok(Some(version.to_string()))
+    }
+
+    /// List all installed versions, newest first
+    pub async fn installed(&self) -> Result<Vec<String>> {
+        let path = self.versions_path();
+        let mut out = Vec::new();
+        let mut dir = match fs::read_dir(&path).await {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(out),
+            Err(err) => {
+                return Err(err).with_context(|| anyhow!("Failed to read directory {:?}", path))
+            }
+        };
+
+        while let Some(entry) = dir.next_entry().await? {
+            if !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            let Some(name) = entry.file_name().to_str().map(String::from) else {
+                continue;
+            };
+            match Self::validate_version(&name) {
+                Ok(()) => out.push(name),
+                Err(_) => trace!("Ignoring {:?} in {:?}", name, path),
+            }
+        }
+
+        out.sort_by_cached_key(|v| std::cmp::Reverse(v.parse::<deb::Version>().ok()));
+        Ok(out)
+    }
+
+    /// Extract a .deb into the directory of its version, replacing it if it already exists
+    pub async fn install<R: Read>(&self, deb: R, version: &str) -> Result<PathBuf> {
+        let install_path = self.version_path(version)?;
+        let new_install_path = self.versions_path().join(format!(".{version}.new"));
+        if fs::metadata(&new_install_path).await.is_ok() {
+            debug!("Removing leftover directory {:?}...", new_install_path);
+            fs::remove_dir_all(&new_install_path)
+                .await
+                .with_context(|| anyhow!("Failed to delete {:?}", new_install_path))?;
+        }
+        extract::pkg(deb, &install_path, &new_install_path).await?;
+        Ok(install_path)
+    }
+
+    /// Atomically point the `current` symlink to an installed version
+    pub async fn activate(&self, version: &str) -> Result<()> {
+        let path = self.version_path(version)?;
+        if !fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+            bail!("Version {:?} is not installed", version);
+        }
+
+        let current = self.current_path();
+        let tmp = self.path.join("current.new");
+        info!("Activating {:?}...", path);
+        fs::remove_file(&tmp).await.ok();
+        fs::symlink(Path::new("versions").join(version), &tmp)
+            .await
+            .with_context(|| anyhow!("Failed to create symlink at {:?}", tmp))?;
+        fs::rename(&tmp, &current)
+            .await
+            .with_context(|| anyhow!("Failed to replace {:?}", current))?;
+        Ok(())
+    }
+
+    /// Delete an installed version and its md5sums
+    pub async fn remove(&self, version: &str) -> Result<()> {
+        let path = self.version_path(version)?;
+        info!("Removing old version {:?}...", path);
+        fs::remove_dir_all(&path)
+            .await
+            .with_context(|| anyhow!("Failed to delete {:?}", path))?;
+        let md5sums = self.md5sums_path(version)?;
+        if let Err(err) = fs::remove_file(&md5sums).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(err).with_context(|| anyhow!("Failed to delete {:?}", md5sums));
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete all installed versions except the active one and the ones in `keep`
+    pub async fn prune(&self, keep: &[String]) -> Result<()> {
+        let current = self.current_version().await?;
+        for version in self.installed().await? {
+            if current.as_ref() == Some(&version) || keep.contains(&version) {
+                continue;
+            }
+            self.remove(&version).await?;
+        }
+        Ok(())
+    }
+
+    /// Move an install directory of an older spotify-launcher release into place
+    pub async fn migrate_legacy(&self, version: &str) -> Result<()> {
+        let legacy = self.path.join("install");
+        if fs::symlink_metadata(self.current_path()).await.is_ok()
+            || !fs::metadata(&legacy).await.is_ok_and(|m| m.is_dir())
+        {
+            return Ok(());
+        }
+
+        let path = self.version_path(version)?;
+        info!("Migrating {:?} to {:?}...", legacy, path);
+        fs::create_dir_all(self.versions_path()).await?;
+        fs::rename(&legacy, &path)
+            .await
+            .with_context(|| anyhow!("Failed to move {:?} to {:?}", legacy, path))?;
+        let md5sums = self.path.join("md5sums");
+        if fs::metadata(&md5sums).await.is_ok() {
+            fs::rename(&md5sums, self.md5sums_path(version)?).await?;
+        }
+        self.activate(version).await
+    }
+}
+
+/// The versions to keep for rollbacks after `version` has been activated, most recent first
+pub fn previous_versions(
+    state: Option<&paths::State>,
+    version: &str,
+    max_versions: usize,
+) -> Vec<String> {
+    let mut previous = Vec::new();
+    if let Some(state) = state {
+        for v in std::iter::once(&state.version).chain(&state.previous_versions) {
+            if v != version && !previous.contains(v) && !state.bad_versions.contains(v) {
+                previous.push(v.clone());
+            }
+        }
+    }
+    // the active version counts towards the limit too
+    previous.truncate(max_versions.saturating_sub(1));
+    previous
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    async fn add_version(installs: &Installs, version: &str) -> Result<()> {
+        let path = installs.version_path(version)?;
+        fs::create_dir_all(path.join("usr/bin")).await?;
+        fs::write(path.join("usr/bin/spotify"), version).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_activate() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let installs = Installs::new(dir.path().to_path_buf());
+        assert_eq!(installs.current_version().await?, None);
+        assert!(installs.activate("1:1.2.3").await.is_err());
+
+        add_version(&installs, "1:1.2.3").await?;
+        add_version(&installs, "1:1.2.4").await?;
+        installs.activate("1:1.2.3").await?;
+        assert_eq!(
+            installs.current_version().await?.as_deref(),
+            Some("1:1.2.3")
+        );
+
+        installs.activate("1:1.2.4").await?;
+        assert_eq!(
+            installs.current_version().await?.as_deref(),
+            Some("1:1.2.4")
+        );
+        let data = fs::read(installs.current_path().join("usr/bin/spotify")).await?;
+        assert_eq!(data, b"1:1.2.4");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_installed_and_prune() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let installs = Installs::new(dir.path().to_path_buf());
+        for version in ["1:1.2.3", "1:1.2.10", "1:1.2.4"] {
+            add_version(&installs, version).await?;
+        }
+        fs::create_dir_all(installs.versions_path().join(".1:1.2.11.new")).await?;
+        assert_eq!(
+            installs.installed().await?,
+            vec!["1:1.2.10", "1:1.2.4", "1:1.2.3"]
+        );
+
+        installs.activate("1:1.2.3").await?;
+        installs.prune(&["1:1.2.4".to_string()]).await?;
+        assert_eq!(installs.installed().await?, vec!["1:1.2.4", "1:1.2.3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_version() {
+        let installs = Installs::new(PathBuf::from("/nonexistent"));
+        assert!(installs.version_path("../../etc").is_err());
+        assert!(installs.version_path("..").is_err());
+        assert!(installs.version_path("1.2/3").is_err());
+        assert!(installs.md5sums_path("").is_err());
+        assert!(installs.version_path("1:1.2.13.661.ga588f749").is_ok());
+    }
+
+    #[test]
+    fn test_previous_versions() {
+        let state = paths::State {
+            version: "1:1.2.4".to_string(),
+            last_update_check: SystemTime::UNIX_EPOCH,
+            release_dates: Default::default(),
+            previous_versions: vec!["1:1.2.3".to_string(), "1:1.2.2".to_string()],
+            bad_versions: vec![],
+            first_launch_pending: false,
+        };
+        assert_eq!(previous_versions(None, "1:1.2.5", 2), Vec::<String>::new());
+        assert_eq!(
+            previous_versions(Some(&state), "1:1.2.5", 3),
+            vec!["1:1.2.4", "1:1.2.3"]
+        );
+        // rolling back swaps the active version with the previous one
+        assert_eq!(
+            previous_versions(Some(&state), "1:1.2.3", 3),
+            vec!["1:1.2.4", "1:1.2.2"]
+        );
+        // reinstalling the active version doesn't change anything
+        assert_eq!(
+            previous_versions(Some(&state), "1:1.2.4", 10),
+            vec!["1:1.2.3", "1:1.2.2"]
+        );
+        assert_eq!(
+            previous_versions(Some(&state), "1:1.2.5", 0),
+            Vec::<String>::new()
+        );
+        // versions that failed to start are never rolled back to
+        let state = paths::State {
+            bad_versions: vec!["1:1.2.4".to_string()],
+            ..state
+        };
+        assert_eq!(
+            previous_versions(Some(&state), "1:1.2.3", 3),
+            vec!["1:1.2.2"]
+        );
+    }
+}
@@ -1,12 +1,19 @@
 pub mod apt;
 pub mod args;
+pub mod cache;
 pub mod config;
 pub mod crypto;
 pub mod deb;
+pub mod deb822;
+pub mod doctor;
 pub mod errors;
 pub mod extract;
 pub mod http;
+pub mod install;
 pub mod paths;
 pub mod pgp;
 pub mod progress;
+pub mod status;
+pub mod supervise;
 pub mod ui;
+pub mod verify;
@@ -1,15 +1,24 @@
 use clap::Parser;
 use env_logger::Env;
+use serde::Serialize;
 use spotify_launcher::apt;
 use spotify_launcher::apt::Client;
-use spotify_launcher::args::Args;
+use spotify_launcher::args::{self, Args, CacheCommand, ListVersions, Run, SubCommand};
+use spotify_launcher::cache::{self, Cache};
 use spotify_launcher::config::ConfigFile;
+use spotify_launcher::deb;
+use spotify_launcher::doctor::{self, Report};
 use spotify_launcher::errors::*;
 use spotify_launcher::extract;
+use spotify_launcher::install::{self, Installs};
 use spotify_launcher::paths;
+use spotify_launcher::status::{self, Status};
+use spotify_launcher::supervise::{self, Launch};
 use spotify_launcher::ui;
+use spotify_launcher::verify;
 use std::ffi::CString;
-use std::path::Path;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::time::SystemTime;
 use tokio::fs;
@@ -17,15 +26,33 @@ use tokio::fs;
 const UPDATE_CHECK_INTERVAL: u64 = 3600 * 24;
 
 struct VersionCheck {
-    deb: Option<Vec<u8>>,
+    deb: Option<PathBuf>,
     version: String,
+    /// The date of the release file we've accepted, with its key in `State::release_dates`
+    release_date: Option<(String, SystemTime)>,
 }
 
-async fn should_update(args: &Args, state: Option<&paths::State>) -> Result<bool> {
-    if args.force_update || args.check_update || args.deb.is_some() {
+async fn should_update(
+    run: &Run,
+    state: Option<&paths::State>,
+    pinned_version: Option<&deb::Version>,
+) -> Result<bool> {
+    if run.force_update || run.check_update || run.deb.is_some() {
         Ok(true)
-    } else if args.skip_update {
+    } else if run.skip_update {
         Ok(false)
+    } else if let (Some(pinned), Some(state)) = (pinned_version, state) {
+        let installed = state.version.parse::<deb::Version>().ok();
+        if installed.as_ref() == Some(pinned) {
+            debug!("Pinned version {} is already installed", pinned);
+            Ok(false)
+        } else {
+            info!(
+                "Installed version {:?} doesn't match pinned version {}",
+                state.version, pinned
+            );
+            Ok(true)
+        }
     } else if let Some(state) = &state {
         let Ok(since_update) = SystemTime::now().duration_since(state.last_update_check) else {
             // if the last update time is somehow in the future, check for updates now
@@ -46,82 +73,812 @@ async fn should_update(args: &Args, state: Option<&paths::State>) -> Result<bool
     }
 }
 
-async fn print_deb_url(args: &Args) -> Result<()> {
-    let client = Client::new(args.timeout)?;
-    let pkg = client.fetch_pkg_release(&args.keyring).await?;
-    println!("{}", pkg.download_url());
+fn apt_client(args: &Args, cf: &ConfigFile) -> Result<Client> {
+    let repository = args
+        .repository
+        .as_deref()
+        .or(cf.spotify.repository.as_deref())
+        .unwrap_or(apt::DEFAULT_REPOSITORY_URL);
+    let suite = args
+        .suite
+        .as_deref()
+        .or(cf.spotify.suite.as_deref())
+        .unwrap_or(apt::DEFAULT_REPOSITORY_SUITE);
+    debug!("Using repository {:?} with suite {:?}", repository, suite);
+    let pgp_backend = args.pgp_backend.unwrap_or(cf.spotify.pgp_backend);
+    debug!("Using pgp backend: {:?}", pgp_backend.resolve());
+    let verifier = pgp_backend.verifier(&args.keyring);
+    let arch = arch(args);
+    debug!("Using architecture: {}", arch);
+    Client::new(args.timeout, repository, suite, arch, verifier)
+}
+
+fn arch(args: &Args) -> deb::Architecture {
+    args.arch.clone().unwrap_or_else(deb::Architecture::current)
+}
+
+fn read_control(args: &Args, deb_path: &Path) -> Result<deb::Control> {
+    let deb = std::fs::File::open(deb_path)
+        .with_context(|| anyhow!("Failed to open .deb file at {:?}", deb_path))?;
+    let control = extract::control(BufReader::new(deb))
+        .with_context(|| anyhow!("Failed to read package metadata from {:?}", deb_path))?;
+    debug!("Parsed control file: {:?}", control);
+    control
+        .validate(&arch(args))
+        .with_context(|| anyhow!("Refusing to install {:?}", deb_path))?;
+    Ok(control)
+}
+
+fn install_path(args: &Args) -> Result<PathBuf> {
+    let install_path = if let Some(path) = &args.install_dir {
+        path.clone()
+    } else {
+        paths::install_path()?
+    };
+    debug!("Using install path: {:?}", install_path);
+    Ok(install_path)
+}
+
+fn download_attempts(args: &Args, cf: &ConfigFile) -> usize {
+    args.download_attempts.unwrap_or_else(|| {
+        cf.spotify
+            .download_attempts
+            .unwrap_or(apt::DEFAULT_DOWNLOAD_ATTEMPTS)
+    })
+}
+
+fn pinned_version(args: &Args, cf: &ConfigFile) -> Result<Option<deb::Version>> {
+    let Some(version) = args
+        .pin_version
+        .as_deref()
+        .or(cf.spotify.pinned_version.as_deref())
+    else {
+        return Ok(None);
+    };
+    let version = version
+        .parse::<deb::Version>()
+        .context("Failed to parse pinned version")?;
+    info!("Using pinned version: {}", version);
+    Ok(Some(version))
+}
+
+/// Move the install directory of an older release into place, before anything is installed or started
+async fn migrate_legacy(args: &Args, state: Option<&paths::State>) -> Result<()> {
+    if let (None, Some(state)) = (&args.install_dir, state) {
+        if let Err(err) = Installs::open()?.migrate_legacy(&state.version).await {
+            warn!("Failed to migrate previous install directory: {err:#}");
+        }
+    }
+    Ok(())
+}
+
+fn last_release_date(client: &Client, state: Option<&paths::State>) -> Option<SystemTime> {
+    state.and_then(|s| s.release_date(client.repository_url(), client.suite()))
+}
+
+async fn run_command(args: &Args, cf: &ConfigFile, run: &Run) -> Result<()> {
+    let install_path = install_path(args)?;
+    let download_attempts = download_attempts(args, cf);
+    let pinned_version = pinned_version(args, cf)?;
+    let state = paths::load_state_file().await?;
+    migrate_legacy(args, state.as_ref()).await?;
+    if should_update(run, state.as_ref(), pinned_version.as_ref()).await? {
+        if let Err(err) = update(
+            args,
+            cf,
+            state.as_ref(),
+            pinned_version.as_ref(),
+            download_attempts,
+            run.deb.as_deref(),
+            run.force_update,
+        )
+        .await
+        {
+            error!("Update failed: {err:#}");
+            ui::error(&err).await?;
+        }
+    } else {
+        info!("No update needed");
+    }
+    start(args, cf, run, &install_path).await
+}
+
+async fn update_command(args: &Args, cf: &ConfigFile, update_args: &args::Update) -> Result<()> {
+    let download_attempts = download_attempts(args, cf);
+    let pinned_version = pinned_version(args, cf)?;
+    let state = paths::load_state_file().await?;
+    migrate_legacy(args, state.as_ref()).await?;
+    update(
+        args,
+        cf,
+        state.as_ref(),
+        pinned_version.as_ref(),
+        download_attempts,
+        None,
+        update_args.force,
+    )
+    .await
+}
+
+async fn install_command(args: &Args, cf: &ConfigFile, install: &args::Install) -> Result<()> {
+    let install_path = install_path(args)?;
+    let download_attempts = download_attempts(args, cf);
+    let pinned_version = pinned_version(args, cf)?;
+    let state = paths::load_state_file().await?;
+    migrate_legacy(args, state.as_ref()).await?;
+    if let Some(state) = &state {
+        if install.deb.is_none() && !install.force && fs::metadata(&install_path).await.is_ok() {
+            info!("Spotify {} is already installed", state.version);
+            return Ok(());
+        }
+    }
+    update(
+        args,
+        cf,
+        state.as_ref(),
+        pinned_version.as_ref(),
+        download_attempts,
+        install.deb.as_deref(),
+        install.force,
+    )
+    .await
+}
+
+async fn print_deb_url(args: &Args, cf: &ConfigFile) -> Result<()> {
+    let pinned_version = pinned_version(args, cf)?;
+    let client = apt_client(args, cf)?;
+    let state = paths::load_state_file().await?;
+    let index = client
+        .fetch_pkg_index(last_release_date(&client, state.as_ref()))
+        .await?;
+    let pkg = index.select_spotify_client(pinned_version.as_ref())?;
+    println!("{}", client.download_url(pkg));
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct AvailableVersion {
+    version: String,
+    size: Option<u64>,
+    sha256: String,
+    url: String,
+    installed: bool,
+}
+
+async fn list_versions(args: &Args, cf: &ConfigFile, list: &ListVersions) -> Result<()> {
+    let client = apt_client(args, cf)?;
+    let state = paths::load_state_file().await?;
+    let index = client
+        .fetch_pkg_index(last_release_date(&client, state.as_ref()))
+        .await?;
+
+    let installed = state.and_then(|s| s.version.parse::<deb::Version>().ok());
+    let mut pkgs = index
+        .pkgs
+        .iter()
+        .filter(|p| p.package == "spotify-client")
+        .map(|p| (p.version.parse::<deb::Version>().ok(), p))
+        .collect::<Vec<_>>();
+    // newest version first, unparsable versions last
+    pkgs.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let versions = pkgs
+        .into_iter()
+        .map(|(version, pkg)| AvailableVersion {
+            version: pkg.version.clone(),
+            size: pkg.size,
+            sha256: pkg.sha256sum.clone(),
+            url: client.download_url(pkg),
+            installed: version.is_some() && version == installed,
+        })
+        .collect::<Vec<_>>();
+
+    if list.json {
+        let json = serde_json::to_string_pretty(&versions)?;
+        println!("{json}");
+    } else {
+        for v in versions {
+            let marker = if v.installed { "*" } else { " " };
+            let size = v
+                .size
+                .map(|size| format!("{:.1} MiB", size as f64 / 1024.0 / 1024.0))
+                .unwrap_or_else(|| "? MiB".to_string());
+            println!("{marker} {} ({size})", v.version);
+            println!("    sha256: {}", v.sha256);
+            println!("    url:    {}", v.url);
+        }
+    }
+
+    Ok(())
+}
+
+async fn status_command(args: &Args, status: &args::Status) -> Result<()> {
+    let install_path = install_path(args)?;
+    let state = paths::load_state_file().await?;
+    let report = Status::collect(
+        state.as_ref(),
+        &install_path,
+        &args.keyring,
+        args.install_dir.is_none(),
+    )
+    .await?;
+
+    if status.json {
+        let json = serde_json::to_string_pretty(&report)?;
+        println!("{json}");
+    } else {
+        report.print();
+    }
+
+    if !report.is_healthy() {
+        bail!("Spotify is not installed or its installation is broken");
+    }
+    Ok(())
+}
+
+async fn uninstall_command(uninstall: &args::Uninstall) -> Result<()> {
+    // a custom --install-dir isn't removed, it isn't necessarily owned by us
+    let mut targets = vec![paths::spotify_launcher_path()?];
+    if uninstall.user_config {
+        targets.push(paths::spotify_config_path()?);
+    }
+    if uninstall.user_cache {
+        targets.push(paths::spotify_cache_path()?);
+    }
+
+    let mut existing = Vec::new();
+    for path in targets {
+        let Ok(metadata) = fs::symlink_metadata(&path).await else {
+            debug!("Nothing to remove at {:?}", path);
+            continue;
+        };
+        let size = if metadata.is_dir() {
+            status::dir_size(&path).await?
+        } else {
+            metadata.len()
+        };
+        existing.push((path, metadata, size));
+    }
+
+    if existing.is_empty() {
+        info!("Nothing to remove");
+        return Ok(());
+    }
+
+    let mut msg = "The following files and directories are going to be removed:\n".to_string();
+    for (path, _, size) in &existing {
+        msg.push_str(&format!(
+            "\n • {} ({:.1} MiB)",
+            path.display(),
+            *size as f64 / 1024.0 / 1024.0
+        ));
+    }
+    println!("{msg}");
+
+    if uninstall.dry_run {
+        return Ok(());
+    }
+    if !uninstall.yes && !ui::confirm(&format!("{msg}\n\nContinue?")).await? {
+        bail!("Uninstall was cancelled");
+    }
+
+    for (path, metadata, _) in existing {
+        info!("Removing {:?}...", path);
+        if metadata.is_dir() {
+            fs::remove_dir_all(&path).await
+        } else {
+            fs::remove_file(&path).await
+        }
+        .with_context(|| anyhow!("Failed to remove {:?}", path))?;
+    }
+    Ok(())
+}
+
+async fn doctor_command(args: &Args) -> Result<()> {
+    let mut report = Report::default();
+
+    let (outcome, cf) = doctor::config();
+    report.push("config file", outcome);
+    report.push("state file", doctor::state_file().await);
+
+    let pgp_backend = args.pgp_backend.unwrap_or(cf.spotify.pgp_backend);
+    report.push("pgp backend", doctor::pgp_backend(pgp_backend));
+    report.push(
+        "zenity",
+        doctor::program("zenity", true, "errors can't be displayed"),
+    );
+    report.push("keyring", doctor::keyring(&args.keyring, pgp_backend));
+
+    let data_dir = paths::spotify_launcher_path()?;
+    report.push("data directory", doctor::data_dir(&data_dir));
+    report.push("free space", doctor::free_space(&data_dir));
+    if let Some(path) = &args.install_dir {
+        report.push("install directory", doctor::data_dir(path));
+        report.push("install free space", doctor::free_space(path));
+    }
+
+    let outcome = match pinned_version(args, &cf).and_then(|pinned| {
+        let client = apt_client(args, &cf)?;
+        Ok((client, pinned))
+    }) {
+        Ok((client, pinned)) => doctor::repository(&client, pinned.as_ref()).await,
+        Err(err) => doctor::Outcome::Fail(format!("{err:#}")),
+    };
+    report.push("repository", outcome);
+
+    report.print();
+    let failures = report.failures();
+    if failures > 0 {
+        bail!("{} of {} checks failed", failures, report.checks.len());
+    }
+    Ok(())
+}
+
+async fn cache_command(cache: &args::Cache) -> Result<()> {
+    match cache.subcommand {
+        CacheCommand::Clean => {
+            let cache = Cache::open()?;
+            let freed = cache.clean().await?;
+            info!(
+                "Removed {:.1} MiB from {:?}",
+                freed as f64 / 1024.0 / 1024.0,
+                cache.path()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn max_installed_versions(cf: &ConfigFile) -> usize {
+    cf.spotify
+        .install_max_versions
+        .unwrap_or(install::DEFAULT_MAX_VERSIONS)
+}
+
+/// The md5sums of the active install, if we know which version that is
+async fn installed_md5sums_path(args: &Args) -> Result<Option<PathBuf>> {
+    if args.install_dir.is_some() {
+        return Ok(Some(paths::md5sums_path()?));
+    }
+    let installs = Installs::open()?;
+    let Some(version) = installs.current_version().await? else {
+        return Ok(None);
+    };
+    Ok(Some(installs.md5sums_path(&version)?))
+}
+
+async fn save_md5sums(deb_path: &Path, path: &Path) -> Result<()> {
+    let deb = std::fs::File::open(deb_path)
+        .with_context(|| anyhow!("Failed to open .deb file at {:?}", deb_path))?;
+    if let Some(md5sums) = extract::md5sums(BufReader::new(deb))? {
+        debug!("Writing md5sums to {:?}", path);
+        fs::write(path, md5sums)
+            .await
+            .with_context(|| anyhow!("Failed to write md5sums to {:?}", path))?;
+    } else {
+        warn!(".deb has no md5sums, installed files can't be verified");
+        if let Err(err) = fs::remove_file(path).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(err).with_context(|| anyhow!("Failed to delete {:?}", path));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn load_md5sums(args: &Args) -> Result<Option<Vec<deb::Md5Sum>>> {
+    let Some(path) = installed_md5sums_path(args).await? else {
+        return Ok(None);
+    };
+    match fs::read_to_string(&path).await {
+        Ok(md5sums) => Ok(Some(deb::parse_md5sums(&md5sums)?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| anyhow!("Failed to read md5sums from {:?}", path)),
+    }
+}
+
+async fn verify_command(args: &Args, cf: &ConfigFile, verify: &args::Verify) -> Result<()> {
+    let install_path = install_path(args)?;
+    let report = if let Some(md5sums) = load_md5sums(args).await? {
+        info!("Verifying {} files in {:?}...", md5sums.len(), install_path);
+        let report = verify::verify_files(&install_path, &md5sums).await?;
+        for path in &report.missing {
+            println!("missing: {}", path.display());
+        }
+        for path in &report.modified {
+            println!("modified: {}", path.display());
+        }
+        println!(
+            "{} files checked, {} missing, {} modified",
+            report.checked,
+            report.missing.len(),
+            report.modified.len()
+        );
+        Some(report)
+    } else {
+        warn!("No md5sums recorded for the installed version");
+        None
+    };
+
+    if report.as_ref().is_some_and(|r| r.is_ok()) {
+        return Ok(());
+    }
+    if !verify.repair {
+        bail!("Installation is corrupted or can't be verified, use --repair to reinstall");
+    }
+
+    let state = paths::load_state_file().await?;
+    migrate_legacy(args, state.as_ref()).await?;
+    let installed = state
+        .as_ref()
+        .map(|state| state.version.parse::<deb::Version>())
+        .transpose()
+        .context("Failed to parse installed version")?;
+    let pinned_version = match installed {
+        Some(installed) => Some(installed),
+        None => pinned_version(args, cf)?,
+    };
+
+    info!("Reinstalling spotify...");
+    update(
+        args,
+        cf,
+        state.as_ref(),
+        pinned_version.as_ref(),
+        download_attempts(args, cf),
+        None,
+        true,
+    )
+    .await?;
+
+    let md5sums = load_md5sums(args)
+        .await?
+        .context("Reinstalled package has no md5sums, can't verify repair")?;
+    let report = verify::verify_files(&install_path, &md5sums).await?;
+    if !report.is_ok() {
+        bail!(
+            "Installation is still corrupted after repair ({} missing, {} modified)",
+            report.missing.len(),
+            report.modified.len()
+        );
+    }
+    println!("Repaired installation, {} files verified", report.checked);
     Ok(())
 }
 
 async fn update(
     args: &Args,
+    cf: &ConfigFile,
     state: Option<&paths::State>,
-    install_path: &Path,
+    pinned_version: Option<&deb::Version>,
     download_attempts: usize,
+    local_deb: Option<&Path>,
+    force: bool,
 ) -> Result<()> {
-    let update = if let Some(deb_path) = &args.deb {
-        let deb = fs::read(deb_path)
-            .await
-            .with_context(|| anyhow!("Failed to read .deb file from {:?}", deb_path))?;
+    let update = if let Some(deb_path) = local_deb {
+        let control = read_control(args, deb_path)?;
+        info!("Installing {} from local .deb", control.version);
         VersionCheck {
-            deb: Some(deb),
-            version: "0".to_string(),
+            deb: Some(deb_path.to_path_buf()),
+            version: control.version,
+            release_date: None,
         }
     } else {
-        let client = Client::new(args.timeout)?;
-        let pkg = client.fetch_pkg_release(&args.keyring).await?;
+        let client = apt_client(args, cf)?;
+        let index = client
+            .fetch_pkg_index(last_release_date(&client, state))
+            .await?;
+        let release_date = index.release_date.map(|date| {
+            let key = paths::State::release_key(client.repository_url(), client.suite());
+            (key, date)
+        });
+        if let Some(msg) = index.signature.expiry_warning(SystemTime::now()) {
+            warn!("{}", msg);
+            if let Err(err) = ui::warning(&msg).await {
+                warn!("Failed to display warning: {:#}", err);
+            }
+        }
+        let pkg = index.select_spotify_client(pinned_version)?;
 
-        match state {
-            Some(state) if state.version == pkg.version && !args.force_update => {
+        let available = pkg.version.parse::<deb::Version>()?;
+        let installed = state.and_then(|state| match state.version.parse::<deb::Version>() {
+            Ok(version) => Some((state, version)),
+            Err(err) => {
+                warn!("Failed to parse installed version: {err:#}");
+                None
+            }
+        });
+
+        match installed {
+            Some((state, installed)) if installed == available && !force => {
                 info!("Latest version is already installed, not updating");
                 VersionCheck {
                     deb: None,
-                    version: pkg.version,
+                    version: state.version.clone(),
+                    release_date,
+                }
+            }
+            Some((state, installed))
+                if installed > available && pinned_version.is_none() && !force =>
+            {
+                warn!(
+                    "Repository offers an older version than the one installed (installed: {}, available: {}), refusing to downgrade (use --force-update to downgrade anyway)",
+                    installed, available
+                );
+                VersionCheck {
+                    deb: None,
+                    version: state.version.clone(),
+                    release_date,
+                }
+            }
+            Some((state, _))
+                if state.bad_versions.contains(&pkg.version)
+                    && pinned_version.is_none()
+                    && !force =>
+            {
+                warn!(
+                    "Version {} crashed after the last update, not updating (use --force-update to install it anyway)",
+                    pkg.version
+                );
+                VersionCheck {
+                    deb: None,
+                    version: state.version.clone(),
+                    release_date,
                 }
             }
             _ => {
-                let deb = client.download_pkg(&pkg, download_attempts).await?;
+                if let Some((_, installed)) = &installed {
+                    if *installed > available {
+                        warn!("Downgrading from {} to {}", installed, available);
+                    }
+                }
+                let cache = Cache::open()?;
+                let deb = if let Some(deb) = cache.lookup(pkg).await? {
+                    info!("Using cached .deb at {:?}", deb);
+                    deb
+                } else {
+                    client.download_pkg(pkg, download_attempts, &cache).await?
+                };
                 VersionCheck {
                     deb: Some(deb),
-                    version: pkg.version,
+                    version: pkg.version.clone(),
+                    release_date,
                 }
             }
         }
     };
 
-    if let Some(deb) = update.deb {
-        extract::pkg(&deb[..], args, install_path).await?;
+    let mut previous_versions = state
+        .map(|s| s.previous_versions.clone())
+        .unwrap_or_default();
+    let mut first_launch_pending = state.is_some_and(|s| s.first_launch_pending);
+    if let Some(deb_path) = update.deb {
+        // a local .deb has been validated already and its version is the one we're installing
+        if local_deb.is_none() {
+            let control = read_control(args, &deb_path)?;
+            if control.version != update.version {
+                bail!(
+                    "Version of .deb doesn't match the package index (expected: {:?}, found: {:?})",
+                    update.version,
+                    control.version
+                );
+            }
+        }
+
+        let deb = std::fs::File::open(&deb_path)
+            .with_context(|| anyhow!("Failed to open .deb file at {:?}", deb_path))?;
+        if let Some(install_path) = &args.install_dir {
+            extract::pkg(BufReader::new(deb), install_path, install_path).await?;
+            save_md5sums(&deb_path, &paths::md5sums_path()?).await?;
+        } else {
+            let installs = Installs::open()?;
+            installs
+                .install(BufReader::new(deb), &update.version)
+                .await?;
+            save_md5sums(&deb_path, &installs.md5sums_path(&update.version)?).await?;
+            installs.activate(&update.version).await?;
+            if state.is_some_and(|s| s.version != update.version) {
+                first_launch_pending = true;
+            }
+
+            previous_versions =
+                install::previous_versions(state, &update.version, max_installed_versions(cf));
+            if let Err(err) = installs.prune(&previous_versions).await {
+                warn!("Failed to clean up old versions: {err:#}");
+            }
+        }
+
+        if local_deb.is_none() {
+            let max_versions = cf
+                .spotify
+                .cache_max_versions
+                .unwrap_or(cache::DEFAULT_MAX_VERSIONS);
+            if let Err(err) = Cache::open()?
+                .evict(max_versions, cf.spotify.cache_max_size)
+                .await
+            {
+                warn!("Failed to clean up cache: {err:#}");
+            }
+        }
+    }
+
+    let mut release_dates = state.map(|s| s.release_dates.clone()).unwrap_or_default();
+    if let Some((key, date)) = update.release_date {
+        release_dates.insert(key, date);
     }
 
     debug!("Updating state file");
-    let buf = serde_json::to_string(&paths::State {
+    paths::save_state_file(&paths::State {
         last_update_check: SystemTime::now(),
         version: update.version,
-    })?;
-    fs::write(paths::state_file_path()?, buf)
-        .await
-        .context("Failed to write state file")?;
+        release_dates,
+        previous_versions,
+        bad_versions: state.map(|s| s.bad_versions.clone()).unwrap_or_default(),
+        first_launch_pending,
+    })
+    .await?;
 
     Ok(())
 }
 
-fn start(args: &Args, cf: &ConfigFile, install_path: &Path) -> Result<()> {
-    let bin = install_path.join("usr/bin/spotify");
-    let bin = CString::new(bin.to_string_lossy().as_bytes())?;
+/// Activate the most recent previous version that's still installed, optionally marking the current one as bad
+///
+/// Returns `None` if there's no previous version to roll back to, the current one is still marked as bad.
+async fn activate_previous(
+    cf: &ConfigFile,
+    mut state: paths::State,
+    mark_bad: bool,
+) -> Result<Option<paths::State>> {
+    let installs = Installs::open()?;
+    let installed = installs.installed().await?;
+    if mark_bad && !state.bad_versions.contains(&state.version) {
+        state.bad_versions.push(state.version.clone());
+    }
+    let Some(version) = state
+        .previous_versions
+        .iter()
+        .find(|v| installed.contains(v) && !state.bad_versions.contains(v))
+        .cloned()
+    else {
+        if mark_bad {
+            paths::save_state_file(&paths::State {
+                first_launch_pending: false,
+                ..state
+            })
+            .await?;
+        }
+        return Ok(None);
+    };
+
+    installs.activate(&version).await?;
+    let state = paths::State {
+        previous_versions: install::previous_versions(
+            Some(&state),
+            &version,
+            max_installed_versions(cf),
+        ),
+        version,
+        first_launch_pending: false,
+        ..state
+    };
+    paths::save_state_file(&state).await?;
+    Ok(Some(state))
+}
+
+async fn rollback(args: &Args, cf: &ConfigFile) -> Result<()> {
+    if args.install_dir.is_some() {
+        bail!("Rollback is not supported with --install-dir");
+    }
+    let state = paths::load_state_file()
+        .await?
+        .context("Spotify is not installed yet")?;
+    let state = activate_previous(cf, state, false)
+        .await?
+        .context("No previous version is installed to roll back to")?;
+
+    info!("Rolled back to {}", state.version);
+    info!("To stay on this version, set `pinned_version` in the config or use --skip-update");
+    Ok(())
+}
+
+/// Start spotify as a child process and roll back if it crashes right away
+async fn supervised_start(
+    cf: &ConfigFile,
+    bin: &Path,
+    exec_args: &[String],
+    state: paths::State,
+) -> Result<()> {
+    let window = Duration::from_secs(
+        cf.spotify
+            .supervise_window
+            .unwrap_or(supervise::DEFAULT_WINDOW),
+    );
+    info!(
+        "Starting spotify {} for the first time, watching it for {} seconds...",
+        state.version,
+        window.as_secs()
+    );
+    let mut cmd = tokio::process::Command::new(bin);
+    cmd.arg0("spotify").args(exec_args);
+
+    let status = match supervise::launch(cmd, window).await? {
+        Launch::Crashed(status) => {
+            let failed = state.version.clone();
+            error!(
+                "Spotify {} exited with {} right after starting",
+                failed, status
+            );
+            let err = match activate_previous(cf, state, true).await {
+                Ok(Some(state)) => {
+                    let msg = format!(
+                        "Spotify {} crashed right after the update ({}).\n\nRolled back to {}, the broken version is going to be skipped by future updates.",
+                        failed, status, state.version
+                    );
+                    warn!("Rolled back to {}", state.version);
+                    if let Err(err) = ui::warning(&msg).await {
+                        warn!("Failed to display warning: {:#}", err);
+                    }
+                    return exec(bin, exec_args);
+                }
+                Ok(None) => anyhow!(
+                    "Spotify {} crashed right after the update ({}) and there is no previous version to roll back to, the broken version is going to be skipped by future updates",
+                    failed,
+                    status
+                ),
+                Err(err) => {
+                    err.context(format!("Spotify {} failed to start ({})", failed, status))
+                }
+            };
+            if let Err(err) = ui::error(&err).await {
+                warn!("Failed to display error: {:#}", err);
+            }
+            return Err(err);
+        }
+        Launch::Exited(status) => {
+            mark_launched(state).await;
+            status
+        }
+        Launch::Running(mut child) => {
+            debug!("Spotify is still running, considering the update successful");
+            mark_launched(state).await;
+            child.wait().await.context("Failed to wait for spotify")?
+        }
+    };
+    std::process::exit(status.code().unwrap_or(1));
+}
 
-    let mut exec_args = vec![CString::new("spotify")?];
+async fn mark_launched(state: paths::State) {
+    let state = paths::State {
+        first_launch_pending: false,
+        ..state
+    };
+    if let Err(err) = paths::save_state_file(&state).await {
+        warn!("Failed to update state file: {err:#}");
+    }
+}
 
-    for arg in cf.spotify.extra_arguments.iter().cloned() {
-        exec_args.push(CString::new(arg)?);
+fn exec(bin: &Path, exec_args: &[String]) -> Result<()> {
+    let path = CString::new(bin.to_string_lossy().as_bytes())?;
+    let mut argv = vec![CString::new("spotify")?];
+    for arg in exec_args {
+        argv.push(CString::new(arg.as_str())?);
     }
+    nix::unistd::execv(&path, &argv).with_context(|| anyhow!("Failed to exec {:?}", bin))?;
+    Ok(())
+}
 
-    if let Some(uri) = &args.uri {
-        exec_args.push(CString::new(format!("--uri={}", uri))?);
+async fn start(args: &Args, cf: &ConfigFile, run: &Run, install_path: &Path) -> Result<()> {
+    let bin = install_path.join("usr/bin/spotify");
+
+    let mut exec_args = cf.spotify.extra_arguments.clone();
+
+    if let Some(uri) = &run.uri {
+        exec_args.push(format!("--uri={}", uri));
     }
 
     debug!("Assembled command: {:?}", exec_args);
 
-    if args.no_exec {
+    if run.no_exec {
         info!("Skipping exec because --no-exec was used");
     } else {
         cf.spotify.extra_env_vars.iter().for_each(|x| {
@@ -131,13 +888,25 @@ fn start(args: &Args, cf: &ConfigFile, install_path: &Path) -> Result<()> {
             };
             std::env::set_var(k, v);
         });
-        nix::unistd::execv(&bin, &exec_args)
-            .with_context(|| anyhow!("Failed to exec {:?}", bin))?;
+
+        if (run.supervise || cf.spotify.supervise_updates) && args.install_dir.is_none() {
+            if let Some(state) = paths::load_state_file().await? {
+                if state.first_launch_pending {
+                    return supervised_start(cf, &bin, &exec_args, state).await;
+                }
+            }
+        }
+        exec(&bin, &exec_args)?;
     }
 
     Ok(())
 }
 
+/// Only the commands that need the config load it, the doctor reports config errors instead
+fn load_config() -> Result<ConfigFile> {
+    ConfigFile::load().context("Failed to load configuration")
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
@@ -150,35 +919,25 @@ async fn main() -> Result<()> {
     };
     env_logger::init_from_env(Env::default().default_filter_or(log_level));
 
-    let cf = ConfigFile::load().context("Failed to load configuration")?;
-
-    let install_path = if let Some(path) = &args.install_dir {
-        path.clone()
-    } else {
-        paths::install_path()?
-    };
-    debug!("Using install path: {:?}", install_path);
-
-    let download_attempts = args.download_attempts.unwrap_or_else(|| {
-        cf.spotify
-            .download_attempts
-            .unwrap_or(apt::DEFAULT_DOWNLOAD_ATTEMPTS)
-    });
+    if args.subcommand.is_some() && args.run != Run::default() {
+        warn!("Options of the run command are ignored with a subcommand, use `spotify-launcher run [OPTIONS]` instead");
+    }
 
-    if args.print_deb_url {
-        print_deb_url(&args).await?;
-    } else {
-        let state = paths::load_state_file().await?;
-        if should_update(&args, state.as_ref()).await? {
-            if let Err(err) = update(&args, state.as_ref(), &install_path, download_attempts).await
-            {
-                error!("Update failed: {err:#}");
-                ui::error(&err).await?;
-            }
-        } else {
-            info!("No update needed");
+    match &args.subcommand {
+        None => run_command(&args, &load_config()?, &args.run).await?,
+        Some(SubCommand::Run(run)) => run_command(&args, &load_config()?, run).await?,
+        Some(SubCommand::Update(update)) => update_command(&args, &load_config()?, update).await?,
+        Some(SubCommand::Install(install)) => {
+            install_command(&args, &load_config()?, install).await?
         }
-        start(&args, &cf, &install_path)?;
+        Some(SubCommand::Status(status)) => status_command(&args, status).await?,
+        Some(SubCommand::Uninstall(uninstall)) => uninstall_command(uninstall).await?,
+        Some(SubCommand::Doctor) => doctor_command(&args).await?,
+        Some(SubCommand::PrintDebUrl) => print_deb_url(&args, &load_config()?).await?,
+        Some(SubCommand::ListVersions(list)) => list_versions(&args, &load_config()?, list).await?,
+        Some(SubCommand::Cache(cache)) => cache_command(cache).await?,
+        Some(SubCommand::Verify(verify)) => verify_command(&args, &load_config()?, verify).await?,
+        Some(SubCommand::Rollback) => rollback(&args, &load_config()?).await?,
     }
 
     Ok(())
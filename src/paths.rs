@@ -1,5 +1,6 @@
 use crate::errors::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::time::SystemTime;
 use tokio::fs;
@@ -9,14 +10,15 @@ pub fn spotify_launcher_path() -> Result<PathBuf> {
     Ok(path.join("spotify-launcher"))
 }
 
+/// Symlink to the active version, see `install::Installs`
 pub fn install_path() -> Result<PathBuf> {
     let path = spotify_launcher_path()?;
-    Ok(path.join("install"))
+    Ok(path.join("current"))
 }
 
-pub fn new_install_path() -> Result<PathBuf> {
+pub fn cache_path() -> Result<PathBuf> {
     let path = spotify_launcher_path()?;
-    Ok(path.join("install-new"))
+    Ok(path.join("cache"))
 }
 
 pub fn state_file_path() -> Result<PathBuf> {
@@ -24,10 +26,52 @@ pub fn state_file_path() -> Result<PathBuf> {
     Ok(path.join("state.json"))
 }
 
+/// Spotify's own configuration, including the user's login
+pub fn spotify_config_path() -> Result<PathBuf> {
+    let path = dirs::config_dir().context("Failed to detect config directory")?;
+    Ok(path.join("spotify"))
+}
+
+/// Spotify's own cache, e.g. downloaded songs
+pub fn spotify_cache_path() -> Result<PathBuf> {
+    let path = dirs::cache_dir().context("Failed to detect cache directory")?;
+    Ok(path.join("spotify"))
+}
+
+/// The md5sums of the package installed with `--install-dir`, copied from its .deb
+pub fn md5sums_path() -> Result<PathBuf> {
+    let path = spotify_launcher_path()?;
+    Ok(path.join("md5sums"))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct State {
     pub version: String,
     pub last_update_check: SystemTime,
+    /// The `Date` of the most recent release file we've accepted, by repository and suite
+    #[serde(default)]
+    pub release_dates: BTreeMap<String, SystemTime>,
+    /// Versions that were active before the current one, most recent first
+    #[serde(default)]
+    pub previous_versions: Vec<String>,
+    /// Versions that crashed right after an update, these are skipped by future updates
+    #[serde(default)]
+    pub bad_versions: Vec<String>,
+    /// The active version was installed by an update and hasn't been started successfully yet
+    #[serde(default)]
+    pub first_launch_pending: bool,
+}
+
+impl State {
+    pub fn release_key(repository_url: &str, suite: &str) -> String {
+        format!("{} {}", repository_url.trim_end_matches('/'), suite)
+    }
+
+    pub fn release_date(&self, repository_url: &str, suite: &str) -> Option<SystemTime> {
+        self.release_dates
+            .get(&Self::release_key(repository_url, suite))
+            .copied()
+    }
 }
 
 pub async fn load_state_file() -> Result<Option<State>> {
@@ -42,7 +86,16 @@ pub async fn load_state_file() -> Result<Option<State>> {
         })?;
         let state = serde_json::from_slice::<State>(&buf);
         debug!("Loaded state: {:?}", state);
-        Ok(state.ok())
+        match state {
+            Ok(state) => Ok(Some(state)),
+            Err(err) => {
+                warn!(
+                    "Ignoring invalid state file at {:?}: {:#}",
+                    state_file_path, err
+                );
+                Ok(None)
+            }
+        }
     } else {
         debug!(
             "State file at {:?} does not exist, using empty state",
@@ -51,3 +104,64 @@ pub async fn load_state_file() -> Result<Option<State>> {
         Ok(None)
     }
 }
+
+/// Like `load_state_file`, but fails if the state file can't be parsed
+pub async fn read_state_file() -> Result<Option<State>> {
+    let state_file_path = state_file_path()?;
+    let buf = match fs::read(&state_file_path).await {
+        Ok(buf) => buf,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| anyhow!("Failed to read state file at {:?}", state_file_path))
+        }
+    };
+    let state = serde_json::from_slice::<State>(&buf)
+        .with_context(|| anyhow!("Failed to parse state file at {:?}", state_file_path))?;
+    Ok(Some(state))
+}
+
+pub async fn save_state_file(state: &State) -> Result<()> {
+    let state_file_path = state_file_path()?;
+    debug!("Writing state file to {:?}...", state_file_path);
+    let buf = serde_json::to_string(state)?;
+    fs::write(&state_file_path, buf)
+        .await
+        .with_context(|| anyhow!("Failed to write state file at {:?}", state_file_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_release_dates() -> Result<()> {
+        let time = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let mut state = serde_json::from_str::<State>(
+            r#"{"version":"1:1.2.3","last_update_check":{"secs_since_epoch":1,"nanos_since_epoch":0}}"#,
+        )?;
+        state.release_dates.insert(
+            State::release_key("https://repository.spotify.com/", "testing"),
+            time(100),
+        );
+        state.release_dates.insert(
+            State::release_key("https://repository.spotify.com", "stable"),
+            time(50),
+        );
+
+        assert_eq!(
+            state.release_date("https://repository.spotify.com", "testing"),
+            Some(time(100))
+        );
+        assert_eq!(
+            state.release_date("https://repository.spotify.com", "stable"),
+            Some(time(50))
+        );
+        assert_eq!(
+            state.release_date("https://mirror.example", "testing"),
+            None
+        );
+        Ok(())
+    }
+}
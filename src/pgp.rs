@@ -1,12 +1,475 @@
 use crate::errors::*;
-use std::path::Path;
+use ::pgp::composed::{Deserializable, DetachedSignature, SignedPublicKey};
+use ::pgp::packet::{PublicKey, PublicSubkey, Signature, SignatureType};
+use ::pgp::ser::Serialize as _;
+use ::pgp::types::{Fingerprint, KeyDetails, KeyId};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::time::{Duration, SystemTime};
 use tokio::process::Command;
 
+/// Warn this long in advance if a signing key or signature is about to expire
+pub const EXPIRY_WARNING_PERIOD: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// Details about a successfully verified signature
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SignatureInfo {
+    /// Fingerprint of the key that made the signature, if it could be identified
+    pub fingerprint: Option<String>,
+    pub key_expires: Option<SystemTime>,
+    pub signature_expires: Option<SystemTime>,
+}
+
+impl SignatureInfo {
+    /// Return a warning if the key or signature is going to expire soon
+    pub fn expiry_warning(&self, now: SystemTime) -> Option<String> {
+        let fingerprint = self.fingerprint.as_deref().unwrap_or("unknown key");
+        let soon = |time: SystemTime| time < now + EXPIRY_WARNING_PERIOD;
+        let days_left = |time: SystemTime| {
+            time.duration_since(now)
+                .map(|d| d.as_secs() / (24 * 3600))
+                .unwrap_or(0)
+        };
+
+        if let Some(expires) = self.key_expires.filter(|t| soon(*t)) {
+            Some(format!(
+                "The pgp key used to sign the spotify repository ({fingerprint}) expires in {} days. Updates are going to fail unless a new key is added to your keyring.",
+                days_left(expires)
+            ))
+        } else {
+            self.signature_expires.filter(|t| soon(*t)).map(|expires| {
+                format!(
+                    "The signature of the spotify repository ({fingerprint}) expires in {} days.",
+                    days_left(expires)
+                )
+            })
+        }
+    }
+}
+
+/// Verifies detached pgp signatures against a keyring
+#[async_trait]
+pub trait Verifier: Send + Sync {
+    async fn verify(&self, sig: &[u8], artifact: &[u8]) -> Result<SignatureInfo>;
+}
+
+/// Which implementation to use for pgp signature verification
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Use `sqv` if it's installed, otherwise fall back to native
+    #[default]
+    Auto,
+    Sqv,
+    Gpgv,
+    Native,
+}
+
+pub fn find_in_path(bin: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(bin))
+        .find(|path| path.is_file())
+}
+
+impl Backend {
+    pub fn resolve(self) -> Backend {
+        match self {
+            Backend::Auto if find_in_path("sqv").is_some() => Backend::Sqv,
+            Backend::Auto => {
+                debug!("Could not find `sqv` in PATH, using native pgp implementation");
+                Backend::Native
+            }
+            backend => backend,
+        }
+    }
+
+    pub fn verifier(self, keyring: &Path) -> Box<dyn Verifier> {
+        let keyring = keyring.to_path_buf();
+        match self.resolve() {
+            Backend::Sqv => Box::new(Sqv { keyring }),
+            Backend::Gpgv => Box::new(Gpgv { keyring }),
+            _ => Box::new(Native { keyring }),
+        }
+    }
+}
+
+/// Write the signature and artifact to a temporary directory, for external tools
+fn write_tmp_files(sig: &[u8], artifact: &[u8]) -> Result<(tempfile::TempDir, PathBuf, PathBuf)> {
+    let tmp = tempfile::tempdir().context("Failed to create temporary directory")?;
+    let tmp_path = tmp.path();
+
+    let artifact_path = tmp_path.join("artifact");
+    fs::write(&artifact_path, artifact)?;
+    let sig_path = tmp_path.join("sig");
+    fs::write(&sig_path, sig)?;
+
+    Ok((tmp, sig_path, artifact_path))
+}
+
+/// Verify signatures with sequoia's `sqv`
+pub struct Sqv {
+    keyring: PathBuf,
+}
+
+#[async_trait]
+impl Verifier for Sqv {
+    async fn verify(&self, sig: &[u8], artifact: &[u8]) -> Result<SignatureInfo> {
+        let (_tmp, sig_path, artifact_path) = write_tmp_files(sig, artifact)?;
+        verify_sig::<&Path>(&sig_path, &artifact_path, &self.keyring).await?;
+        Ok(describe_with_keyring(sig, &self.keyring))
+    }
+}
+
+/// Verify signatures with gnupg's `gpgv`
+pub struct Gpgv {
+    keyring: PathBuf,
+}
+
+#[async_trait]
+impl Verifier for Gpgv {
+    async fn verify(&self, sig: &[u8], artifact: &[u8]) -> Result<SignatureInfo> {
+        let (tmp, sig_path, artifact_path) = write_tmp_files(sig, artifact)?;
+
+        // gpgv can't read ascii armored keyrings, convert to binary first
+        let keys = match load_keyring(&self.keyring) {
+            Ok(keys) => Some(keys),
+            Err(err) => {
+                warn!("Failed to load keyring, passing it to gpgv as-is: {err:#}");
+                None
+            }
+        };
+        let keyring_paths = if let Some(keys) = &keys {
+            let mut keyring = Vec::new();
+            for key in keys {
+                key.to_writer(&mut keyring)
+                    .context("Failed to serialize pgp key")?;
+            }
+            let keyring_path = tmp.path().join("keyring.gpg");
+            fs::write(&keyring_path, keyring)?;
+            vec![keyring_path]
+        } else {
+            keyring_files(&self.keyring)?
+        };
+
+        let mut cmd = Command::new("gpgv");
+        cmd.arg("--homedir").arg(tmp.path());
+        for keyring_path in &keyring_paths {
+            cmd.arg("--keyring").arg(keyring_path);
+        }
+        let mut cmd = cmd
+            .arg("--")
+            .arg(&sig_path)
+            .arg(&artifact_path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to run `gpgv`")?;
+
+        let exit = cmd
+            .wait()
+            .await
+            .context("Failed to wait for `gpgv` child process")?;
+
+        if exit.success() {
+            Ok(keys
+                .map(|keys| describe_signature(sig, &keys))
+                .unwrap_or_default())
+        } else {
+            bail!("Verification of pgp signature didn't succeed");
+        }
+    }
+}
+
+/// Verify signatures in-process, without any external tools
+pub struct Native {
+    keyring: PathBuf,
+}
+
+#[async_trait]
+impl Verifier for Native {
+    async fn verify(&self, sig: &[u8], artifact: &[u8]) -> Result<SignatureInfo> {
+        verify_sig_native(sig, artifact, &self.keyring)
+    }
+}
+
+/// List the keyring files to use, the keyring can either be a single file or a directory of keys
+pub fn keyring_files(keyring: &Path) -> Result<Vec<PathBuf>> {
+    if !keyring.is_dir() {
+        return Ok(vec![keyring.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(keyring)
+        .with_context(|| anyhow!("Failed to read keyring directory {:?}", keyring))?
+    {
+        let path = entry?.path();
+        let ext = path.extension().and_then(|e| e.to_str());
+        if path.is_file() && matches!(ext, Some("gpg" | "asc" | "pgp")) {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    if files.is_empty() {
+        bail!("Keyring directory {:?} doesn't contain any keys", keyring);
+    }
+    Ok(files)
+}
+
+/// Load all usable keys, files and keys that can't be used are skipped with a warning
+pub fn load_keyring(keyring: &Path) -> Result<Vec<SignedPublicKey>> {
+    let mut out = Vec::new();
+    for path in keyring_files(keyring)? {
+        let buf = match fs::read(&path) {
+            Ok(buf) => buf,
+            Err(err) => {
+                warn!("Ignoring keyring at {:?}, failed to read it: {}", path, err);
+                continue;
+            }
+        };
+        let keys = match SignedPublicKey::from_reader_many(&buf[..]) {
+            Ok((keys, _)) => keys,
+            Err(err) => {
+                warn!(
+                    "Ignoring keyring at {:?}, failed to parse it: {}",
+                    path, err
+                );
+                continue;
+            }
+        };
+
+        for key in keys {
+            let key = match key {
+                Ok(key) => key,
+                Err(err) => {
+                    warn!(
+                        "Ignoring key in keyring {:?}, failed to parse it: {}",
+                        path, err
+                    );
+                    continue;
+                }
+            };
+            if let Err(err) = key.verify_bindings() {
+                warn!(
+                    "Ignoring key {} with invalid self-signatures: {}",
+                    key.fingerprint(),
+                    err
+                );
+                continue;
+            }
+            if !key.details.revocation_signatures.is_empty() {
+                warn!("Ignoring revoked key {}", key.fingerprint());
+                continue;
+            }
+            debug!("Loaded pgp key {} from {:?}", key.fingerprint(), path);
+            out.push(key);
+        }
+    }
+    if out.is_empty() {
+        bail!("No usable keys in keyring at {:?}", keyring);
+    }
+    Ok(out)
+}
+
+fn expiration(created: impl Into<SystemTime>, sigs: &[&Signature]) -> Option<SystemTime> {
+    // the most recent self-signature is authoritative
+    let sig = sigs
+        .iter()
+        .max_by_key(|s| s.created().map(|c| c.as_secs()))?;
+    let expires = sig.key_expiration_time()?.as_secs();
+    if expires == 0 {
+        None
+    } else {
+        Some(created.into() + Duration::from_secs(expires.into()))
+    }
+}
+
+enum KeyRef<'a> {
+    Primary(&'a PublicKey),
+    Subkey(&'a PublicSubkey),
+}
+
+/// A key or subkey that can be used to verify signatures
+struct SigningKey<'a> {
+    key: KeyRef<'a>,
+    primary: &'a SignedPublicKey,
+    expires: Option<SystemTime>,
+}
+
+impl SigningKey<'_> {
+    fn fingerprint(&self) -> Fingerprint {
+        match self.key {
+            KeyRef::Primary(key) => key.fingerprint(),
+            KeyRef::Subkey(key) => key.fingerprint(),
+        }
+    }
+
+    fn key_id(&self) -> KeyId {
+        match self.key {
+            KeyRef::Primary(key) => key.legacy_key_id(),
+            KeyRef::Subkey(key) => key.legacy_key_id(),
+        }
+    }
+
+    fn verify(&self, sig: &Signature, artifact: &[u8]) -> bool {
+        match self.key {
+            KeyRef::Primary(key) => sig.verify(key, artifact).is_ok(),
+            KeyRef::Subkey(key) => sig.verify(key, artifact).is_ok(),
+        }
+    }
+}
+
+fn signing_keys(keys: &[SignedPublicKey]) -> Vec<SigningKey<'_>> {
+    let mut out = Vec::new();
+    for key in keys {
+        let self_sigs = key
+            .details
+            .users
+            .iter()
+            .flat_map(|u| &u.signatures)
+            .chain(&key.details.direct_signatures)
+            .collect::<Vec<_>>();
+        out.push(SigningKey {
+            key: KeyRef::Primary(&key.primary_key),
+            primary: key,
+            expires: expiration(key.primary_key.created_at(), &self_sigs),
+        });
+
+        for subkey in &key.public_subkeys {
+            let revoked = subkey
+                .signatures
+                .iter()
+                .any(|s| s.typ() == Some(SignatureType::SubkeyRevocation));
+            let bindings = subkey
+                .signatures
+                .iter()
+                .filter(|s| s.typ() == Some(SignatureType::SubkeyBinding))
+                .collect::<Vec<_>>();
+            let can_sign = bindings.iter().any(|s| s.key_flags().sign());
+            if revoked || !can_sign {
+                continue;
+            }
+            out.push(SigningKey {
+                key: KeyRef::Subkey(&subkey.key),
+                primary: key,
+                expires: expiration(subkey.key.created_at(), &bindings),
+            });
+        }
+    }
+    out
+}
+
+fn signature_expiration(sig: &Signature) -> Option<SystemTime> {
+    let created = SystemTime::from(sig.created()?);
+    let expires = sig.signature_expiration_time()?.as_secs();
+    if expires == 0 {
+        None
+    } else {
+        Some(created + Duration::from_secs(expires.into()))
+    }
+}
+
+fn signature_info(sig: &Signature, key: &SigningKey) -> SignatureInfo {
+    let fingerprint = format!("{:X}", key.fingerprint());
+    let primary = format!("{:X}", key.primary.fingerprint());
+    if fingerprint == primary {
+        info!("Found valid signature from key {}", fingerprint);
+    } else {
+        info!(
+            "Found valid signature from subkey {} of {}",
+            fingerprint, primary
+        );
+    }
+
+    SignatureInfo {
+        fingerprint: Some(fingerprint),
+        key_expires: key.expires,
+        signature_expires: signature_expiration(sig),
+    }
+}
+
+/// Like [`describe_signature`], loading the keys from a keyring first
+fn describe_with_keyring(sig: &[u8], keyring: &Path) -> SignatureInfo {
+    match load_keyring(keyring) {
+        Ok(keys) => describe_signature(sig, &keys),
+        Err(err) => {
+            warn!("Failed to load keyring to describe signature: {err:#}");
+            SignatureInfo::default()
+        }
+    }
+}
+
+/// Describe a signature that has already been verified by an external tool
+///
+/// This is only informational, if rpgp can't parse the signature the verification still stands.
+fn describe_signature(sig: &[u8], keys: &[SignedPublicKey]) -> SignatureInfo {
+    let sig = match DetachedSignature::from_reader_single(sig) {
+        Ok((sig, _)) => sig.signature,
+        Err(err) => {
+            warn!("Failed to parse pgp signature to describe it: {err:#}");
+            return SignatureInfo::default();
+        }
+    };
+
+    let issuer_fingerprints = sig.issuer_fingerprint();
+    let issuer_key_ids = sig.issuer_key_id();
+    let signer = signing_keys(keys).into_iter().find(|k| {
+        issuer_fingerprints.contains(&&k.fingerprint()) || issuer_key_ids.contains(&&k.key_id())
+    });
+
+    if let Some(signer) = signer {
+        signature_info(&sig, &signer)
+    } else {
+        warn!("Could not identify the key that made the signature");
+        SignatureInfo {
+            signature_expires: signature_expiration(&sig),
+            ..Default::default()
+        }
+    }
+}
+
+/// Verify a detached signature in-process, without any external tools
+pub fn verify_sig_native(sig: &[u8], artifact: &[u8], keyring: &Path) -> Result<SignatureInfo> {
+    let keys = load_keyring(keyring)?;
+    let (sig, _) =
+        DetachedSignature::from_reader_single(sig).context("Failed to parse pgp signature")?;
+    let sig = sig.signature;
+
+    let now = SystemTime::now();
+    if signature_expiration(&sig).is_some_and(|expires| expires < now) {
+        bail!("Pgp signature has expired");
+    }
+
+    for key in signing_keys(&keys) {
+        if !key.verify(&sig, artifact) {
+            continue;
+        }
+        let created = sig.created().map(SystemTime::from);
+        if let (Some(expires), Some(created)) = (key.expires, created) {
+            if created > expires {
+                warn!(
+                    "Ignoring signature from key {:X}, the signature was made after the key expired",
+                    key.fingerprint()
+                );
+                continue;
+            }
+        }
+        return Ok(signature_info(&sig, &key));
+    }
+
+    bail!("Verification of pgp signature didn't succeed");
+}
+
 pub async fn verify_sig<P: AsRef<Path>>(sig: P, artifact: P, keyring: P) -> Result<()> {
-    let mut cmd = Command::new("sqv")
-        .arg("--keyring")
-        .arg(keyring.as_ref())
+    let mut cmd = Command::new("sqv");
+    for keyring in keyring_files(keyring.as_ref())? {
+        cmd.arg("--keyring").arg(keyring);
+    }
+    let mut cmd = cmd
         .arg("--")
         .arg(sig.as_ref())
         .arg(artifact.as_ref())
@@ -26,10 +489,247 @@ pub async fn verify_sig<P: AsRef<Path>>(sig: P, artifact: P, keyring: P) -> Resu
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct ClearSigned {
+    pub text: String,
+    pub signature: String,
+}
+
+/// Split a clearsigned message (like an apt `InRelease` file) into the signed text and its signature.
+///
+/// The text is returned with dash-escaping and trailing whitespace removed and without
+/// the line break that separates it from the signature, exactly as covered by the signature.
+pub fn parse_clearsigned(data: &str) -> Result<ClearSigned> {
+    let mut lines = data.lines();
+
+    if lines.next().map(str::trim_end) != Some("-----BEGIN PGP SIGNED MESSAGE-----") {
+        bail!("Clearsigned message doesn't start with the expected header");
+    }
+
+    // skip armor headers like `Hash: SHA512`
+    for line in lines.by_ref() {
+        if line.trim_end().is_empty() {
+            break;
+        }
+        if !line.contains(": ") {
+            bail!("Malformed armor header in clearsigned message: {:?}", line);
+        }
+    }
+
+    let mut text = Vec::new();
+    let mut found_signature = false;
+    for line in lines.by_ref() {
+        if line.trim_end() == "-----BEGIN PGP SIGNATURE-----" {
+            found_signature = true;
+            break;
+        }
+        let line = line.strip_prefix("- ").unwrap_or(line);
+        // trailing whitespace isn't covered by the signature (RFC 4880 section 7.1)
+        text.push(line.trim_end_matches([' ', '\t']));
+    }
+    if !found_signature {
+        bail!("Clearsigned message is missing its signature");
+    }
+
+    let mut signature = String::from("-----BEGIN PGP SIGNATURE-----\n");
+    let mut found_end = false;
+    for line in lines {
+        signature.push_str(line);
+        signature.push('\n');
+        if line.trim_end() == "-----END PGP SIGNATURE-----" {
+            found_end = true;
+            break;
+        }
+    }
+    if !found_end {
+        bail!("Signature in clearsigned message is truncated");
+    }
+
+    Ok(ClearSigned {
+        text: text.join("\n"),
+        signature,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_clearsigned() -> Result<()> {
+        let data = "-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA512
+
+Origin: Spotify LTD
+- -- dash escaped
+Suite: testing
+-----BEGIN PGP SIGNATURE-----
+
+iQIzBAEBCgAdFiEE
+=abcd
+-----END PGP SIGNATURE-----
+";
+        let parsed = parse_clearsigned(data)?;
+        assert_eq!(
+            parsed,
+            ClearSigned {
+                text: "Origin: Spotify LTD\n-- dash escaped\nSuite: testing".to_string(),
+                signature: "-----BEGIN PGP SIGNATURE-----\n\niQIzBAEBCgAdFiEE\n=abcd\n-----END PGP SIGNATURE-----\n".to_string(),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_clearsigned_missing_signature() {
+        let data = "-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA512
+
+Origin: Spotify LTD
+";
+        assert!(parse_clearsigned(data).is_err());
+    }
+
+    #[test]
+    fn test_parse_clearsigned_trailing_whitespace() -> Result<()> {
+        let data = fs::read_to_string("data/InRelease-trailing-whitespace")?;
+        let parsed = parse_clearsigned(&data)?;
+        assert_eq!(
+            parsed.text,
+            "Origin: Spotify LTD\nLabel: Spotify Public Repository\nSuite: testing"
+        );
+        verify_sig_native(
+            parsed.signature.as_bytes(),
+            parsed.text.as_bytes(),
+            Path::new("data/test-key.gpg"),
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_not_clearsigned() {
+        assert!(parse_clearsigned("Origin: Spotify LTD\n").is_err());
+    }
+
+    #[test]
+    fn test_verify_native() -> Result<()> {
+        let sig = fs::read("data/Release.gpg")?;
+        let artifact = fs::read("data/Release")?;
+        let info = verify_sig_native(
+            &sig,
+            &artifact,
+            Path::new("data/pubkey_5384CE82BA52C83A.gpg"),
+        )?;
+        assert_eq!(
+            info.fingerprint.as_deref(),
+            Some("E1096BCBFF6D418796DE78515384CE82BA52C83A")
+        );
+        assert!(info.key_expires.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyring_dir() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        assert!(keyring_files(dir.path()).is_err());
+
+        fs::copy(
+            "data/pubkey_5384CE82BA52C83A.gpg",
+            dir.path().join("spotify.asc"),
+        )?;
+        fs::write(dir.path().join("README"), "not a key")?;
+        assert_eq!(
+            keyring_files(dir.path())?,
+            vec![dir.path().join("spotify.asc")]
+        );
+
+        // a stray file that isn't a key doesn't break the other keys
+        fs::write(dir.path().join("broken.gpg"), "not a key")?;
+        assert_eq!(load_keyring(dir.path())?.len(), 1);
+
+        let sig = fs::read("data/Release.gpg")?;
+        let artifact = fs::read("data/Release")?;
+        verify_sig_native(&sig, &artifact, dir.path())?;
+
+        fs::remove_file(dir.path().join("spotify.asc"))?;
+        assert!(load_keyring(dir.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_signature() -> Result<()> {
+        let sig = fs::read("data/Release.gpg")?;
+        let keys = load_keyring(Path::new("data/pubkey_5384CE82BA52C83A.gpg"))?;
+        let info = describe_signature(&sig, &keys);
+        assert_eq!(
+            info.fingerprint.as_deref(),
+            Some("E1096BCBFF6D418796DE78515384CE82BA52C83A")
+        );
+        assert_eq!(describe_signature(&sig, &[]).fingerprint, None);
+        // describing is best-effort, errors don't fail an otherwise valid verification
+        assert_eq!(
+            describe_signature(b"not a signature", &keys),
+            SignatureInfo::default()
+        );
+        assert_eq!(
+            describe_with_keyring(&sig, Path::new("data/Release")),
+            SignatureInfo::default()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiry_warning() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let day = Duration::from_secs(24 * 3600);
+
+        let mut info = SignatureInfo {
+            fingerprint: Some("E1096BCBFF6D418796DE78515384CE82BA52C83A".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(info.expiry_warning(now), None);
+
+        info.key_expires = Some(now + day * 90);
+        assert_eq!(info.expiry_warning(now), None);
+
+        info.key_expires = Some(now + day * 10);
+        let warning = info.expiry_warning(now).unwrap();
+        assert!(warning.contains("expires in 10 days"), "{warning}");
+        assert!(warning.contains("E1096BCBFF6D418796DE78515384CE82BA52C83A"));
+
+        info.key_expires = None;
+        info.signature_expires = Some(now + day * 2);
+        let warning = info.expiry_warning(now).unwrap();
+        assert!(warning.contains("signature"), "{warning}");
+    }
+
+    #[test]
+    fn test_verify_native_tampered() -> Result<()> {
+        let sig = fs::read("data/Release.gpg")?;
+        let mut artifact = fs::read("data/Release")?;
+        artifact.extend(b"Foo: bar\n");
+        let result = verify_sig_native(
+            &sig,
+            &artifact,
+            Path::new("data/pubkey_5384CE82BA52C83A.gpg"),
+        );
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_gpgv() -> Result<()> {
+        let sig = fs::read("data/Release.gpg")?;
+        let artifact = fs::read("data/Release")?;
+        let verifier = Backend::Gpgv.verifier(Path::new("data/pubkey_5384CE82BA52C83A.gpg"));
+        verifier.verify(&sig, &artifact).await?;
+
+        let mut artifact = artifact;
+        artifact.extend(b"Foo: bar\n");
+        assert!(verifier.verify(&sig, &artifact).await.is_err());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_verify() -> Result<()> {
         verify_sig(
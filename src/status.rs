@@ -0,0 +1,158 @@
+use crate::config::ConfigFile;
+use crate::deb;
+use crate::errors::*;
+use crate::install::Installs;
+use crate::paths;
+use serde::Serialize;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// What's installed and whether it looks usable
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub version: Option<String>,
+    pub last_update_check: Option<String>,
+    pub first_launch_pending: bool,
+    pub previous_versions: Vec<String>,
+    pub bad_versions: Vec<String>,
+    pub installed_versions: Vec<String>,
+    pub install_path: PathBuf,
+    pub install_size: Option<u64>,
+    pub binary: PathBuf,
+    pub binary_exists: bool,
+    pub binary_executable: bool,
+    pub config_file: Option<PathBuf>,
+    pub keyring: PathBuf,
+    pub keyring_exists: bool,
+}
+
+impl Status {
+    pub async fn collect(
+        state: Option<&paths::State>,
+        install_path: &Path,
+        keyring: &Path,
+        versioned: bool,
+    ) -> Result<Status> {
+        let installed_versions = if versioned {
+            Installs::open()?.installed().await?
+        } else {
+            Vec::new()
+        };
+        let install_size = if fs::metadata(install_path).await.is_ok() {
+            Some(dir_size(install_path).await?)
+        } else {
+            None
+        };
+
+        let binary = install_path.join("usr/bin/spotify");
+        let metadata = fs::metadata(&binary).await.ok();
+        let binary_exists = metadata.as_ref().is_some_and(|m| m.is_file());
+        let binary_executable = metadata
+            .as_ref()
+            .is_some_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+
+        Ok(Status {
+            version: state.map(|s| s.version.clone()),
+            last_update_check: state.map(|s| deb::format_date(s.last_update_check)),
+            first_launch_pending: state.is_some_and(|s| s.first_launch_pending),
+            previous_versions: state
+                .map(|s| s.previous_versions.clone())
+                .unwrap_or_default(),
+            bad_versions: state.map(|s| s.bad_versions.clone()).unwrap_or_default(),
+            installed_versions,
+            install_path: install_path.to_path_buf(),
+            install_size,
+            binary,
+            binary_exists,
+            binary_executable,
+            config_file: ConfigFile::locate_file()?,
+            keyring: keyring.to_path_buf(),
+            keyring_exists: fs::metadata(keyring).await.is_ok(),
+        })
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.version.is_some() && self.binary_executable
+    }
+
+    pub fn print(&self) {
+        let none = || "-".to_string();
+        let list = |v: &[String]| {
+            if v.is_empty() {
+                none()
+            } else {
+                v.join(", ")
+            }
+        };
+
+        println!(
+            "version:            {}",
+            self.version.clone().unwrap_or_else(none)
+        );
+        println!(
+            "last update check:  {}",
+            self.last_update_check.clone().unwrap_or_else(none)
+        );
+        if self.first_launch_pending {
+            println!("                    (not started since the last update)");
+        }
+        println!("installed versions: {}", list(&self.installed_versions));
+        println!("previous versions:  {}", list(&self.previous_versions));
+        println!("bad versions:       {}", list(&self.bad_versions));
+        let size = self
+            .install_size
+            .map(|size| format!("{:.1} MiB", size as f64 / 1024.0 / 1024.0))
+            .unwrap_or_else(|| "missing".to_string());
+        println!("install path:       {:?} ({size})", self.install_path);
+        let binary = if self.binary_executable {
+            "ok"
+        } else if self.binary_exists {
+            "not executable"
+        } else {
+            "missing"
+        };
+        println!("binary:             {:?} ({binary})", self.binary);
+        match &self.config_file {
+            Some(path) => println!("config file:        {:?}", path),
+            None => println!("config file:        - (using default config)"),
+        }
+        let keyring = if self.keyring_exists { "ok" } else { "missing" };
+        println!("keyring:            {:?} ({keyring})", self.keyring);
+    }
+}
+
+/// The size of all files in a directory, without following symlinks inside of it
+pub async fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let mut dir = fs::read_dir(&path)
+            .await
+            .with_context(|| anyhow!("Failed to read directory {:?}", path))?;
+        while let Some(entry) = dir.next_entry().await? {
+            let metadata = fs::symlink_metadata(entry.path()).await?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                size += metadata.len();
+            }
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dir_size() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("usr/bin")).await?;
+        fs::write(dir.path().join("usr/bin/spotify"), b"ohai").await?;
+        fs::write(dir.path().join("README"), b"hello world").await?;
+        assert_eq!(dir_size(dir.path()).await?, 15);
+        Ok(())
+    }
+}
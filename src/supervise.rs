@@ -0,0 +1,68 @@
+use crate::errors::*;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+/// How long spotify needs to keep running after an update until it's considered working
+pub const DEFAULT_WINDOW: u64 = 30;
+
+#[derive(Debug)]
+pub enum Launch {
+    /// The process exited abnormally before the window was over
+    Crashed(ExitStatus),
+    /// The process exited cleanly before the window was over
+    Exited(ExitStatus),
+    /// The process is still running after the window
+    Running(Child),
+}
+
+/// Spawn a process and watch it for the given amount of time
+pub async fn launch(mut cmd: Command, window: Duration) -> Result<Launch> {
+    let mut child = cmd.spawn().context("Failed to start spotify")?;
+    match tokio::time::timeout(window, child.wait()).await {
+        Ok(status) => {
+            let status = status.context("Failed to wait for spotify")?;
+            if status.success() {
+                Ok(Launch::Exited(status))
+            } else {
+                Ok(Launch::Crashed(status))
+            }
+        }
+        Err(_) => Ok(Launch::Running(child)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        cmd
+    }
+
+    #[tokio::test]
+    async fn test_launch() -> Result<()> {
+        let window = Duration::from_secs(10);
+        assert!(matches!(
+            launch(sh("exit 0"), window).await?,
+            Launch::Exited(_)
+        ));
+        assert!(matches!(
+            launch(sh("exit 1"), window).await?,
+            Launch::Crashed(_)
+        ));
+        assert!(matches!(
+            launch(sh("kill -SEGV $$"), window).await?,
+            Launch::Crashed(_)
+        ));
+
+        let Launch::Running(mut child) = launch(sh("sleep 10"), Duration::from_millis(100)).await?
+        else {
+            panic!("Expected process to still be running");
+        };
+        child.kill().await?;
+        Ok(())
+    }
+}
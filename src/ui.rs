@@ -40,3 +40,16 @@ pub async fn error(err: &Error) -> Result<()> {
     ui.child.wait().await?;
     Ok(())
 }
+
+/// Ask a yes/no question, returns `true` if the user confirmed
+pub async fn confirm(msg: &str) -> Result<bool> {
+    let mut ui = Zenity::spawn(&["--question", "--no-markup", "--text", msg])?;
+    let status = ui.child.wait().await?;
+    Ok(status.success())
+}
+
+pub async fn warning(msg: &str) -> Result<()> {
+    let mut ui = Zenity::spawn(&["--warning", "--no-markup", "--text", msg])?;
+    ui.child.wait().await?;
+    Ok(())
+}
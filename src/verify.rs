@@ -0,0 +1,110 @@
+use crate::crypto;
+use crate::deb::Md5Sum;
+use crate::errors::*;
+use md5::{Digest, Md5};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// The result of checking the installed files against the package's md5sums
+#[derive(Debug, PartialEq, Default)]
+pub struct Report {
+    pub checked: usize,
+    pub missing: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty()
+    }
+}
+
+pub async fn md5_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .await
+        .with_context(|| anyhow!("Failed to open {:?}", path))?;
+    let mut hasher = Md5::new();
+    crypto::hash_reader(&mut file, |bytes| hasher.update(bytes))
+        .await
+        .with_context(|| anyhow!("Failed to read {:?}", path))?;
+    Ok(crypto::md5sum_hex(hasher))
+}
+
+/// Check every file listed in the md5sums against the install directory
+pub async fn verify_files(install_path: &Path, md5sums: &[Md5Sum]) -> Result<Report> {
+    let mut report = Report::default();
+    for entry in md5sums {
+        let path = install_path.join(&entry.path);
+        report.checked += 1;
+
+        match fs::symlink_metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => (),
+            Ok(_) => {
+                debug!("Not a regular file: {:?}", path);
+                report.modified.push(entry.path.clone());
+                continue;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                debug!("Missing file: {:?}", path);
+                report.missing.push(entry.path.clone());
+                continue;
+            }
+            Err(err) => {
+                return Err(err).with_context(|| anyhow!("Failed to access {:?}", path));
+            }
+        }
+
+        let md5sum = md5_file(&path).await?;
+        if md5sum != entry.md5sum {
+            debug!(
+                "Checksum mismatch for {:?} (expected: {:?}, found: {:?})",
+                path, entry.md5sum, md5sum
+            );
+            report.modified.push(entry.path.clone());
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("usr/bin"))?;
+        std::fs::write(dir.path().join("usr/bin/spotify"), b"ohai!")?;
+        std::fs::write(dir.path().join("usr/bin/modified"), b"ohno!")?;
+
+        let md5sums = vec![
+            Md5Sum {
+                md5sum: "36252c01906b7993eabdc504fdf8af96".to_string(),
+                path: PathBuf::from("usr/bin/spotify"),
+            },
+            Md5Sum {
+                md5sum: "36252c01906b7993eabdc504fdf8af96".to_string(),
+                path: PathBuf::from("usr/bin/modified"),
+            },
+            Md5Sum {
+                md5sum: "36252c01906b7993eabdc504fdf8af96".to_string(),
+                path: PathBuf::from("usr/bin/missing"),
+            },
+        ];
+
+        let report = verify_files(dir.path(), &md5sums).await?;
+        assert_eq!(
+            report,
+            Report {
+                checked: 3,
+                missing: vec![PathBuf::from("usr/bin/missing")],
+                modified: vec![PathBuf::from("usr/bin/modified")],
+            }
+        );
+        assert!(!report.is_ok());
+
+        let report = verify_files(dir.path(), &md5sums[..1]).await?;
+        assert!(report.is_ok());
+        Ok(())
+    }
+}